@@ -1,8 +1,11 @@
-use crate::layout::LayoutType;
-use crate::wm::WM;
+use crate::filter::{self, WindowFilter};
+use crate::layout::{FocusNew, LayoutType, Placement};
+use crate::screen::Direction;
+use crate::wm::{MouseAction, WM};
 
 use std::process::Command;
 use std::thread;
+use std::time::Duration;
 
 use xcb::xproto;
 use x11::keysym;
@@ -11,17 +14,140 @@ use x11::keysym;
 pub const WIN_WIDTH_MIN: u16 = 200;
 pub const WIN_HEIGHT_MIN: u16 = 100;
 
-// Number of workspaces to have
+// Look of windows, border colors and width, and gaps between tiled windows (inner) and around them (outer)
+pub struct Theme {
+    pub border_focused: u32,
+    pub border_unfocused: u32,
+    pub border_urgent: u32,
+    pub border_width: u32,
+    pub gap_inner: u32,
+    pub gap_outer: u32,
+}
+
+// A border width of 0 skips all border requests (e.g. when a compositor highlights focus)
+pub const THEME: Theme = Theme {
+    border_focused: 0x66d9ef,
+    border_unfocused: 0x1d1f21,
+    border_urgent: 0xf92672,
+    border_width: 2,
+    gap_inner: 0,
+    gap_outer: 0,
+};
+
+// Window opacity (0.0 - 1.0) when focused / unfocused, requires a compositor
+pub const ACTIVE_OPACITY: f32 = 1.0;
+pub const INACTIVE_OPACITY: f32 = 1.0;
+
+// Default fraction of screen width given to the master window when tiling,
+// with its bounds and the step to adjust by
+pub const MASTER_FACTOR: f32 = 0.5;
+pub const MASTER_FACTOR_MIN: f32 = 0.1;
+pub const MASTER_FACTOR_MAX: f32 = 0.9;
+pub const MASTER_FACTOR_STEP: f32 = 0.05;
+
+// Bounds of the fraction of a bsp split given to either side, and the step to adjust by
+pub const BSP_RATIO_MIN: f32 = 0.1;
+pub const BSP_RATIO_MAX: f32 = 0.9;
+pub const BSP_RATIO_STEP: f32 = 0.05;
+
+// Default number of windows sharing the master area when tiling
+pub const MASTER_COUNT: usize = 1;
+
+// When tiling, a lone tiled window covers the whole screen without a border (monocle-like), back to tiled once joined
+pub const AUTO_FULLSCREEN_SINGLE: bool = false;
+
+// Whether focus follows the focused window when rotating the stack, else it stays in the same slot
+pub const ROTATE_FOCUS_FOLLOWS_WINDOW: bool = true;
+
+// Layouts stepped through in order when cycling, wrapping back to the first
+pub const LAYOUT_CYCLE: &[LayoutType] = &[LayoutType::Tiling, LayoutType::Bsp, LayoutType::Floating];
+
+// Placement of new floating windows, and offset between cascaded windows
+pub const PLACEMENT: Placement = Placement::Smart;
+pub const CASCADE_OFFSET: i32 = 32;
+
+// Focusing of new windows, those not focused are marked urgent instead
+pub const FOCUS_NEW_WINDOWS: FocusNew = FocusNew::Smart;
+
+// Listen on an IPC socket, $XDG_RUNTIME_DIR/afwm$DISPLAY.sock, e.g. for bars to subscribe to events
+pub const IPC_SOCKET: bool = true;
+
+// Write a status summary to the root window name, for xsetroot-style bars
+pub const STATUS_ROOT_NAME: bool = false;
+
+// Warp pointer to the center of windows (or monitors) focused by keybind, so focus-follows-mouse doesn't snap back
+pub const WARP_POINTER_ON_FOCUS: bool = true;
+
+// Number of workspaces to have, the minimum kept if dynamic. Can be overridden at runtime with --workspaces,
+// within WORKSPACES_MIN - WORKSPACES_MAX
 pub const WORKSPACES: usize = 9;
+pub const WORKSPACES_MIN: usize = 1;
+pub const WORKSPACES_MAX: usize = 32;
+
+// Create workspaces on demand when all are full, up to a maximum, and remove them again once empty
+pub const DYNAMIC_WORKSPACES: bool = false;
+pub const MAX_WORKSPACES: usize = 16;
+
+// Maximum tiled windows per workspace, new windows overflow into the next workspace with room
+pub const MAX_WINDOWS_PER_WORKSPACE: Option<usize> = None;
+
+// Workspace names, as shown by pagers. Workspaces beyond these are named by number
+pub const WORKSPACE_NAMES: &[&str] = &["1", "2", "3", "4", "5", "6", "7", "8", "9"];
+
+// Slide workspaces horizontally when switching, in a number of steps
+pub const WORKSPACE_ANIMATION: bool = false;
+pub const WORKSPACE_ANIMATION_STEPS: i32 = 10;
+pub const WORKSPACE_ANIMATION_STEP_TIME: Duration = Duration::from_millis(16);
+
+// Minimum time between configures while dragging a window to move / resize it, so as not to
+// flood the X server (or a compositor). The final position is always applied. Zero disables
+pub const DRAG_THROTTLE_TIME: Duration = Duration::from_millis(8);
+
+// Wrap-around when moving to next / previous workspace, else clamp at the ends
+pub const WORKSPACES_WRAP: bool = true;
+
+// Rules applied in order to windows being mapped, deciding whether they're managed, floated or
+// left alone, see filter.rs
+pub const WINDOW_FILTERS: &[&dyn WindowFilter] = &[
+    &filter::OverrideRedirect,
+    &filter::WindowType,
+    &filter::TransientFor,
+];
+
+// Scratchpad window class / instance name, and command spawning it if not yet running
+pub const SCRATCHPAD_CLASS: &str = "scratchpad";
+pub const SCRATCHPAD_COMMAND: &[&str] = &["alacritty", "--class", SCRATCHPAD_CLASS];
+
+// Commands run once started, e.g. compositor, status bar, applets
+pub const STARTUP_COMMANDS: &[&[&str]] = &[
+    // &["picom"],
+    // &["nm-applet"],
+];
+
+// Only run startup commands on first start, not when restarted in place, so daemons aren't started twice
+pub const STARTUP_ONCE: bool = true;
+
+// Launcher taking the keyboard exclusively while open, must map a normal (not override-redirect) window
+// setting _NET_WM_PID, which is how its window is told apart
+pub const LAUNCHER_COMMAND: &[&str] = &["rofi", "-show", "run", "-normal-window"];
 
-// Modifier key for keybinds
+// Modifier key for keybinds, can be overridden at runtime with --modkey (e.g. "alt", "super", "mod1")
 pub const MODKEY: u32 = xproto::MOD_MASK_4;
 
-// Key binds of tuple: (mask, key, function)
-pub const KEYBINDS: &[(xcb::ModMask, xcb::Keysym, fn(&mut WM))] = &[
+// Key bind tuple: (mask, key, function)
+pub type Keybind = (xcb::ModMask, xcb::Keysym, fn(&mut WM));
+
+// Time allowed to press the second key of a chord
+pub const CHORD_TIMEOUT: Duration = Duration::from_millis(2000);
+
+// Key binds
+pub const KEYBINDS: &[Keybind] = &[
     // Dmenu
     (MODKEY, keysym::XK_p, |_|{ run(&["dmenu_run", "-fn", "Ubuntu Mono:size=12", "-nb", "#1d1f21", "-nf", "#66d9ef", "-sb", "#1d1f21", "-sf", "#66d9ef"]) }),
 
+    // Modal launcher, holding the keyboard until closed
+    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_p, |wm|{ run_modal(wm, LAUNCHER_COMMAND) }),
+
     // Backlight keys
     (0, keysym::XF86XK_MonBrightnessUp,   |_|{ run(&["xbacklight", "-inc", "5"]) }),
     (0, keysym::XF86XK_MonBrightnessDown, |_|{ run(&["xbacklight", "-dec", "5"]) }),
@@ -35,43 +161,149 @@ pub const KEYBINDS: &[(xcb::ModMask, xcb::Keysym, fn(&mut WM))] = &[
     // Launch terminal
     (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_Return, |_|{ run(&["alacritty"]) }),
 
+    // Toggle scratchpad terminal
+    (MODKEY, keysym::XK_minus, |wm|{ toggle_scratchpad(wm) }),
+
     // Close focused window
     (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_c, |wm|{ close_focused_window(wm) }),
 
-    // Kill window manager
-    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_q, |wm|{ wm.kill() }),
+    // Quit window manager, or restart it in place keeping windows where they are
+    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_q, |wm|{ wm.quit() }),
+    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_r, |wm|{ wm.restart() }),
+
+    // Kill window manager immediately, skipping all cleanup but releasing key / button grabs
+    (MODKEY|xproto::MOD_MASK_SHIFT|xproto::MOD_MASK_CONTROL, keysym::XK_q, |wm|{ wm.kill() }),
 
-    // Switch focused window
-    (MODKEY, keysym::XK_Tab, |wm| { wm.desktop.current_mut().window_focus_cycle(&wm.conn, &wm.screen) }),
+    // Toggle focused window fullscreen
+    (MODKEY, keysym::XK_f, |wm|{ wm.desktop.current_mut().toggle_fullscreen_focused(&wm.conn, &wm.screen, wm.theme) }),
+
+    // Toggle focused window always ontop, e.g. a floating video while working
+    (MODKEY, keysym::XK_a, |wm|{ wm.toggle_above() }),
+
+    // Toggle focused window sticky, i.e. shown on all workspaces
+    (MODKEY, keysym::XK_s, |wm|{ wm.desktop.toggle_sticky(&wm.conn) }),
+
+    // Switch focused window, most recently used first while the modkey is held, or in order
+    (MODKEY, keysym::XK_Tab, |wm| { mru_cycle(wm) }),
+    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_Tab, |wm| { focus_cycle(wm) }),
+
+    // Swap focused window with master
+    (MODKEY, keysym::XK_Return, |wm|{ wm.desktop.current_mut().swap_master(&wm.conn, &wm.screen) }),
+
+    // Rotate window order, master moving to the bottom of the stack, or the bottom window into master
+    (MODKEY, keysym::XK_r, |wm|{ wm.desktop.current_mut().rotate_left(&wm.conn, &wm.screen) }),
+    (MODKEY|xproto::MOD_MASK_CONTROL, keysym::XK_r, |wm|{ wm.desktop.current_mut().rotate_right(&wm.conn, &wm.screen) }),
+
+    // Raise / lower focused window within its layer
+    (MODKEY, keysym::XK_Prior, |wm|{ wm.desktop.current_mut().raise_focused(&wm.conn) }),
+    (MODKEY, keysym::XK_Next, |wm|{ wm.desktop.current_mut().lower_focused(&wm.conn) }),
+
+    // Move focus to the adjacent monitor
+    (MODKEY|xproto::MOD_MASK_CONTROL, keysym::XK_h, |wm|{ wm.focus_monitor(Direction::Left) }),
+    (MODKEY|xproto::MOD_MASK_CONTROL, keysym::XK_j, |wm|{ wm.focus_monitor(Direction::Down) }),
+    (MODKEY|xproto::MOD_MASK_CONTROL, keysym::XK_k, |wm|{ wm.focus_monitor(Direction::Up) }),
+    (MODKEY|xproto::MOD_MASK_CONTROL, keysym::XK_l, |wm|{ wm.focus_monitor(Direction::Right) }),
+
+    // Move focus to the next monitor, wrapping around
+    (MODKEY, keysym::XK_o, |wm|{ wm.focus_next_monitor() }),
+
+    // Move focused window to the adjacent monitor, following it
+    (MODKEY|xproto::MOD_MASK_CONTROL|xproto::MOD_MASK_SHIFT, keysym::XK_h, |wm|{ wm.move_window_to_monitor(Direction::Left) }),
+    (MODKEY|xproto::MOD_MASK_CONTROL|xproto::MOD_MASK_SHIFT, keysym::XK_j, |wm|{ wm.move_window_to_monitor(Direction::Down) }),
+    (MODKEY|xproto::MOD_MASK_CONTROL|xproto::MOD_MASK_SHIFT, keysym::XK_k, |wm|{ wm.move_window_to_monitor(Direction::Up) }),
+    (MODKEY|xproto::MOD_MASK_CONTROL|xproto::MOD_MASK_SHIFT, keysym::XK_l, |wm|{ wm.move_window_to_monitor(Direction::Right) }),
+    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_o, |wm|{ wm.move_window_to_next_monitor() }),
+
+    // Promote focused window to master, pushing the rest down the stack
+    (MODKEY|xproto::MOD_MASK_CONTROL, keysym::XK_Return, |wm|{ wm.desktop.current_mut().promote_focused(&wm.conn, &wm.screen) }),
 
     // Workspace switching
-    (MODKEY, keysym::XK_1, |wm|{ wm.desktop.goto(&wm.conn, &wm.screen, 0) }),
-    (MODKEY, keysym::XK_2, |wm|{ wm.desktop.goto(&wm.conn, &wm.screen, 1) }),
-    (MODKEY, keysym::XK_3, |wm|{ wm.desktop.goto(&wm.conn, &wm.screen, 2) }),
-    (MODKEY, keysym::XK_4, |wm|{ wm.desktop.goto(&wm.conn, &wm.screen, 3) }),
-    (MODKEY, keysym::XK_5, |wm|{ wm.desktop.goto(&wm.conn, &wm.screen, 4) }),
-    (MODKEY, keysym::XK_6, |wm|{ wm.desktop.goto(&wm.conn, &wm.screen, 5) }),
-    (MODKEY, keysym::XK_7, |wm|{ wm.desktop.goto(&wm.conn, &wm.screen, 6) }),
-    (MODKEY, keysym::XK_8, |wm|{ wm.desktop.goto(&wm.conn, &wm.screen, 7) }),
-    (MODKEY, keysym::XK_9, |wm|{ wm.desktop.goto(&wm.conn, &wm.screen, 8) }),
-    (MODKEY, keysym::XK_Left,  |wm|{ wm.desktop.goto(&wm.conn, &wm.screen, wm.desktop.index_prev()) }),
-    (MODKEY, keysym::XK_Right, |wm|{ wm.desktop.goto(&wm.conn, &wm.screen, wm.desktop.index_next()) }),
+    (MODKEY, keysym::XK_Left,  |wm|{ wm.goto(wm.desktop.index_prev()) }),
+    (MODKEY, keysym::XK_Right, |wm|{ wm.goto(wm.desktop.index_next()) }),
+    (MODKEY, keysym::XK_grave, |wm|{ wm.goto_prev() }),
+    (MODKEY, keysym::XK_u,     |wm|{ goto_urgent(wm) }),
+
+    // Adding a new workspace (and going to it), removing the current one if empty
+    (MODKEY, keysym::XK_n, |wm|{ wm.add_workspace() }),
+    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_BackSpace, |wm|{ wm.remove_workspace() }),
+
+    // Reordering workspaces
+    (MODKEY|xproto::MOD_MASK_CONTROL, keysym::XK_Left,  |wm|{ wm.desktop.move_workspace_left(&wm.conn, &wm.screen) }),
+    (MODKEY|xproto::MOD_MASK_CONTROL, keysym::XK_Right, |wm|{ wm.desktop.move_workspace_right(&wm.conn, &wm.screen) }),
 
     // Sending windows to workspaces
-    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_1, |wm|{ send_window_from_workspace_to(wm, 0) } ),
-    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_2, |wm|{ send_window_from_workspace_to(wm, 1) } ),
-    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_3, |wm|{ send_window_from_workspace_to(wm, 2) } ),
-    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_4, |wm|{ send_window_from_workspace_to(wm, 3) } ),
-    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_5, |wm|{ send_window_from_workspace_to(wm, 4) } ),
-    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_6, |wm|{ send_window_from_workspace_to(wm, 5) } ),
-    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_7, |wm|{ send_window_from_workspace_to(wm, 6) } ),
-    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_8, |wm|{ send_window_from_workspace_to(wm, 7) } ),
-    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_9, |wm|{ send_window_from_workspace_to(wm, 8) } ),
-    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_Left,  |wm|{ send_window_from_workspace_to(wm, wm.desktop.index_prev()) } ),
-    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_Right, |wm|{ send_window_from_workspace_to(wm, wm.desktop.index_next()) } ),
+    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_Left,  |wm|{ wm.send_window_to_workspace(wm.desktop.index_prev()) } ),
+    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_Right, |wm|{ wm.send_window_to_workspace(wm.desktop.index_next()) } ),
 
     // Set current workspace window layout
-    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_f, |wm|{ wm.desktop.current_mut().set_layout(&wm.conn, &wm.screen, LayoutType::Floating) } ),
+    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_f, |wm|{ wm.desktop.set_layout(&wm.conn, &wm.screen, LayoutType::Floating) } ),
+    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_t, |wm|{ wm.desktop.set_layout(&wm.conn, &wm.screen, LayoutType::Tiling) } ),
+    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_b, |wm|{ wm.desktop.set_layout(&wm.conn, &wm.screen, LayoutType::Bsp) } ),
+
+    // Cycle current workspace window layout, see LAYOUT_CYCLE
+    (MODKEY, keysym::XK_space, |wm|{ wm.desktop.cycle_layout(&wm.conn, &wm.screen) } ),
+
+    // Adjust current workspace master factor
+    (MODKEY, keysym::XK_h, |wm|{ wm.desktop.adjust_master_factor(&wm.conn, &wm.screen, -MASTER_FACTOR_STEP) } ),
+    (MODKEY, keysym::XK_l, |wm|{ wm.desktop.adjust_master_factor(&wm.conn, &wm.screen, MASTER_FACTOR_STEP) } ),
+
+    // Adjust current workspace master window count
+    (MODKEY, keysym::XK_i, |wm|{ wm.desktop.adjust_master_count(&wm.conn, &wm.screen, 1) } ),
+    (MODKEY, keysym::XK_d, |wm|{ wm.desktop.adjust_master_count(&wm.conn, &wm.screen, -1) } ),
+
+    // Rotate the bsp split holding the focused window, or grow / shrink the focused window's side of it
+    (MODKEY, keysym::XK_e, |wm|{ wm.desktop.current_mut().rotate_split_focused(&wm.conn, &wm.screen) } ),
+    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_l, |wm|{ wm.desktop.current_mut().adjust_split_focused(&wm.conn, &wm.screen, true) } ),
+    (MODKEY|xproto::MOD_MASK_SHIFT, keysym::XK_h, |wm|{ wm.desktop.current_mut().adjust_split_focused(&wm.conn, &wm.screen, false) } ),
+
+    // Reset current workspace master factor and count (or bsp splits) so tiled windows share the screen evenly
+    (MODKEY, keysym::XK_equal, |wm|{ wm.desktop.balance(&wm.conn, &wm.screen) } ),
+];
+
+// Workspace key bind tuple: (mask, function called with the workspace index)
+pub type WorkspaceKeybind = (xcb::ModMask, fn(&mut WM, usize));
+
+// Keys for each workspace in order, bound for as many workspaces as there are
+pub const WORKSPACE_KEYS: &[xcb::Keysym] = &[
+    keysym::XK_1, keysym::XK_2, keysym::XK_3, keysym::XK_4, keysym::XK_5,
+    keysym::XK_6, keysym::XK_7, keysym::XK_8, keysym::XK_9, keysym::XK_0,
+];
+
+// Key binds repeated for each of WORKSPACE_KEYS
+pub const WORKSPACE_KEYBINDS: &[WorkspaceKeybind] = &[
+    // Workspace switching
+    (MODKEY, |wm, idx|{ wm.goto(idx) }),
+
+    // Sending windows to workspaces
+    (MODKEY|xproto::MOD_MASK_SHIFT, |wm, idx|{ wm.send_window_to_workspace(idx) }),
+];
+
+// Mouse bind tuple: (mask, button, action)
+pub type Mousebind = (xcb::ModMask, xcb::ButtonIndex, MouseAction);
+
+// Mouse binds, either dragging the clicked window or momentary actions e.g. scrolling.
+// Only grabbed with a modifier on the root window, so don't interfere with normal app mouse use
+pub const MOUSEBINDS: &[Mousebind] = &[
+    // Drag to move / resize clicked window
+    (MODKEY, xcb::BUTTON_INDEX_1, MouseAction::Move),
+    (MODKEY, xcb::BUTTON_INDEX_3, MouseAction::Resize),
+
+    // Close window under the pointer (focus follows the mouse)
+    (MODKEY, xcb::BUTTON_INDEX_2, MouseAction::Call(|wm|{ close_focused_window(wm) })),
+
+    // Workspace switching with scroll wheel
+    (MODKEY, xcb::BUTTON_INDEX_4, MouseAction::Call(|wm|{ wm.goto(wm.desktop.index_next()) })),
+    (MODKEY, xcb::BUTTON_INDEX_5, MouseAction::Call(|wm|{ wm.goto(wm.desktop.index_prev()) })),
+];
+
+// Key chords of tuple: (prefix mask, prefix key, key binds to match following key press)
+pub const CHORDS: &[(xcb::ModMask, xcb::Keysym, &[Keybind])] = &[
+    // Set current workspace window layout, e.g. mod+w then t
+    (MODKEY, keysym::XK_w, &[
+        (0, keysym::XK_f, |wm|{ wm.desktop.set_layout(&wm.conn, &wm.screen, LayoutType::Floating) } ),
+        (0, keysym::XK_t, |wm|{ wm.desktop.set_layout(&wm.conn, &wm.screen, LayoutType::Tiling) } ),
+        (0, keysym::XK_b, |wm|{ wm.desktop.set_layout(&wm.conn, &wm.screen, LayoutType::Bsp) } ),
+    ]),
 ];
 
 // If there is a currently focused window, send a kill client command via X
@@ -81,33 +313,95 @@ fn close_focused_window(wm: &mut WM) {
     }
 }
 
-// If there is a currently focused window, sends from current workspace to workspace at index
-fn send_window_from_workspace_to(wm: &mut WM, idx: usize) {
-    if let Some(focused) = wm.desktop.current_mut().window_del_focused(&wm.conn, &wm.screen) {
-        // Remove this window from current workspace
-        wm.desktop.get_mut(idx).windows.add(focused);
+// Cycle focused window, warping the pointer to it if configured
+fn focus_cycle(wm: &mut WM) {
+    wm.desktop.current_mut().window_focus_cycle(&wm.conn, &wm.screen);
+    warp_to_focused(wm);
+}
+
+// Cycle focused window by most recent use, warping the pointer to it if configured
+fn mru_cycle(wm: &mut WM) {
+    wm.mru_cycle();
+    warp_to_focused(wm);
+}
+
+// Goto first workspace with an urgent window and focus it
+fn goto_urgent(wm: &mut WM) {
+    if wm.goto_urgent() {
+        warp_to_focused(wm);
     }
 }
 
-// Run an argument array in new thread, waiting for exit status
+// Warp pointer to the center of the focused window if configured, so focus-follows-mouse agrees with keyboard focus
+fn warp_to_focused(wm: &mut WM) {
+    if WARP_POINTER_ON_FOCUS {
+        if let Some(focused) = wm.desktop.current().windows.focused() {
+            let (x, y) = focused.xwindow.center();
+            wm.conn.warp_pointer(focused.xwindow.id, x, y);
+        }
+    }
+}
+
+// Toggle the scratchpad window, spawning it if not yet captured
+fn toggle_scratchpad(wm: &mut WM) {
+    if wm.desktop.scratchpad.window.is_none() {
+        run(SCRATCHPAD_COMMAND);
+    } else {
+        wm.desktop.scratchpad_toggle(&wm.conn);
+    }
+}
+
+// Run each startup command, without waiting on any
+pub fn run_startup_commands() {
+    for args in STARTUP_COMMANDS {
+        info!("Running startup command: {:?}", args);
+        run(args);
+    }
+}
+
+// Run an argument array as with run, its window taking the keyboard once mapped
+fn run_modal(wm: &mut WM, args: &'static [&str]) {
+    if let Some(pid) = spawn(args) {
+        wm.expect_modal(pid);
+    }
+}
+
+// Run an argument array, waiting for exit status in new thread
 fn run(args: &'static [&str]) {
-    thread::spawn(move || {
-        // Log
-        debug!("Running command: {:?}", args);
+    spawn(args);
+}
 
-        // Create new Command object
-        let mut cmd = Command::new(args[0]);
+// Run an argument array as with run, returning its process id if it started
+fn spawn(args: &'static [&str]) -> Option<u32> {
+    // Log
+    debug!("Running command: {:?}", args);
 
-        // Set arguments
-        cmd.args(args.iter().skip(1));
+    // Create new Command object
+    let mut cmd = Command::new(args[0]);
 
-        // Execute!
-        match cmd.status() {
-            // Executed and returned exit status. Log returned status
-            Ok(status) => debug!("{:?}: exited with {}", args, status),
+    // Set arguments
+    cmd.args(args.iter().skip(1));
 
-            // Did not execute. Log returned error
-            Err(err) => warn!("{:?}: {}", args, err),
-        }
-    });
+    // Execute!
+    match cmd.spawn() {
+        Ok(mut child) => {
+            let pid = child.id();
+            thread::spawn(move || {
+                match child.wait() {
+                    // Returned exit status. Log returned status
+                    Ok(status) => debug!("{:?}: exited with {}", args, status),
+
+                    // Failed waiting. Log returned error
+                    Err(err) => warn!("{:?}: {}", args, err),
+                }
+            });
+            return Some(pid);
+        },
+
+        // Did not execute. Log returned error
+        Err(err) => {
+            warn!("{:?}: {}", args, err);
+            return None;
+        },
+    }
 }
\ No newline at end of file
@@ -1,46 +1,712 @@
-use crate::config::WORKSPACES;
+use crate::config::{DYNAMIC_WORKSPACES, LAYOUT_CYCLE, MAX_WORKSPACES, WORKSPACE_ANIMATION, WORKSPACE_ANIMATION_STEPS, WORKSPACE_ANIMATION_STEP_TIME, WORKSPACE_NAMES, WORKSPACES_MAX, WORKSPACES_WRAP};
+use crate::helper;
+use crate::layout::LayoutType;
+use crate::scratchpad::Scratchpad;
+use crate::windows::{SavedWindow, Window};
 use crate::screen::Screen;
 use crate::workspace::Workspace;
 use crate::x::{XConn, XWindowID};
 
-#[derive(Default)]
+use std::collections::HashMap;
+use std::time::Instant;
+
+// In-progress workspace switching animation
+struct Transition {
+    // Outgoing workspace index
+    from: usize,
+
+    // Slide direction, 1 = incoming from the right, -1 = from the left
+    direction: i32,
+
+    // Current step, and time of last step
+    step: i32,
+    last_step: Instant,
+}
+
 pub struct Desktop {
     // Internal workspace tracking
-    workspaces: [Workspace; WORKSPACES],
+    workspaces: Vec<Workspace>,
+
+    // Number of workspaces to have, the minimum kept if dynamic
+    count: usize,
 
     // Current workspace index
     idx: usize,
+
+    // Previously active workspace index
+    idx_prev: usize,
+
+    // Workspace index shown on each other monitor, by monitor index. Current workspace is on the focused monitor
+    visible: HashMap<usize, usize>,
+
+    // Workspace switching animation, if in-progress
+    transition: Option<Transition>,
+
+    // Scratchpad window, shown over whichever workspace is current
+    pub scratchpad: Scratchpad,
 }
 
 impl Desktop {
+    pub fn new(count: usize) -> Self {
+        // Name workspaces from config, else by number
+        let mut workspaces = Vec::with_capacity(count);
+        for idx in 0..count {
+            let mut ws = Workspace::default();
+            ws.name = WORKSPACE_NAMES.get(idx).map_or((idx + 1).to_string(), |name| name.to_string());
+            workspaces.push(ws);
+        }
+
+        Self {
+            workspaces: workspaces,
+            count: count,
+            idx: 0,
+            idx_prev: 0,
+            visible: HashMap::new(),
+            transition: None,
+            scratchpad: Scratchpad::default(),
+        }
+    }
+
+    pub fn publish(&self, conn: &XConn, screen: &Screen) {
+        // Advertise workspaces to EWMH pagers
+        conn.set_number_of_desktops(screen.idx, self.workspaces.len());
+        self.publish_names(conn, screen);
+        conn.set_current_desktop(screen.idx, self.idx);
+        self.publish_layout(conn, screen);
+    }
+
+    pub fn rename(&mut self, conn: &XConn, screen: &Screen, name: &str) {
+        // Back to numbered when given no name
+        let idx = self.idx;
+        let name = if name.is_empty() { (idx + 1).to_string() } else { name.to_owned() };
+        debug!("Renaming workspace {}: {}", idx, name);
+        self.current_mut().name = name;
+        self.publish_names(conn, screen);
+    }
+
+    fn publish_names(&self, conn: &XConn, screen: &Screen) {
+        let names: Vec<&str> = self.workspaces.iter().map(|ws| ws.name.as_str()).collect();
+        conn.set_desktop_names(screen.idx, &names);
+    }
+
+    fn publish_layout(&self, conn: &XConn, screen: &Screen) {
+        // Current workspace's layout name on the root window, for status bars
+        conn.set_utf8_string(screen.xwindow.id, conn.atoms.LAYOUT_NAME, self.current().layout.name());
+    }
+
+    pub fn serialize(&self) -> String {
+        // Line per workspace, then line per window, see Workspace::serialize and Window::serialize
+        let mut state = String::new();
+        for (idx, ws) in self.workspaces.iter().enumerate() {
+            state.push_str(&ws.serialize(idx));
+            state.push('\n');
+        }
+        for (idx, ws) in self.workspaces.iter().enumerate() {
+            for window in ws.windows.iter() {
+                state.push_str(&window.serialize(idx));
+                state.push('\n');
+            }
+        }
+        return state;
+    }
+
+    pub fn restore(&mut self, conn: &XConn, screen: &Screen, state: &str) -> HashMap<XWindowID, SavedWindow> {
+        // Workspaces are restored here, windows are returned to be restored as they're adopted
+        let mut windows = HashMap::new();
+        for line in state.lines() {
+            if let Some((window_id, saved)) = Window::deserialize(line) {
+                windows.insert(window_id, saved);
+                continue;
+            }
+            let saved = match Workspace::deserialize(line) {
+                Some(saved) => saved,
+                None => {
+                    warn!("Skipping malformed state line: {}", line);
+                    continue;
+                },
+            };
+
+            // Recreate any dynamic workspace, dropped again later if nothing lands on it
+            while saved.idx >= self.workspaces.len() && self.create_workspace(conn, screen).is_some() {}
+            if let Some(ws) = self.workspaces.get_mut(saved.idx) {
+                ws.set_layout(conn, screen, saved.layout);
+                ws.set_master_factor(conn, screen, saved.master_factor);
+
+                // Set directly, windows aren't adopted yet so there's nothing to clamp against
+                ws.master_count = saved.master_count;
+            }
+        }
+        return windows;
+    }
+
+    pub fn set_layout(&mut self, conn: &XConn, screen: &Screen, layout: LayoutType) {
+        self.current_mut().set_layout(conn, screen, layout);
+        self.publish_layout(conn, screen);
+    }
+
+    pub fn cycle_layout(&mut self, conn: &XConn, screen: &Screen) {
+        // Next layout in the configured cycle, starting over from the first if not in it
+        let next = match LAYOUT_CYCLE.iter().position(|layout| *layout == self.current().layout) {
+            Some(idx) => LAYOUT_CYCLE[(idx + 1) % LAYOUT_CYCLE.len()],
+            None => match LAYOUT_CYCLE.first() {
+                Some(layout) => *layout,
+                None => return,
+            },
+        };
+        self.set_layout(conn, screen, next);
+    }
+
+    pub fn adjust_master_factor(&mut self, conn: &XConn, screen: &Screen, delta: f32) {
+        let factor = self.current().master_factor + delta;
+        self.current_mut().set_master_factor(conn, screen, factor);
+    }
+
+    pub fn adjust_master_count(&mut self, conn: &XConn, screen: &Screen, delta: i32) {
+        let count = (self.current().master_count as i32 + delta).max(1) as usize;
+        self.current_mut().set_master_count(conn, screen, count);
+    }
+
+    pub fn balance(&mut self, conn: &XConn, screen: &Screen) {
+        self.current_mut().balance(conn, screen);
+    }
+
+    pub fn status(&self) -> String {
+        let mut status = String::new();
+
+        // Workspace indicators, [active] and occupied*
+        for (idx, ws) in self.workspaces.iter().enumerate() {
+            if idx == self.idx {
+                status.push_str(&format!("[{}] ", ws.name));
+            } else if !ws.windows.is_empty() {
+                status.push_str(&format!("{}* ", ws.name));
+            } else {
+                status.push_str(&format!("{} ", ws.name));
+            }
+        }
+
+        // Layout symbol
+        status.push_str(&format!("| {}", self.current().layout.symbol()));
+
+        // Focused window title
+        if let Some(focused) = self.current().windows.focused() {
+            status.push_str(&format!(" | {}", focused.title));
+        }
+
+        return status;
+    }
+
+    pub fn index_with_room(&mut self, conn: &XConn, screen: &Screen) -> usize {
+        // First workspace from current onwards that isn't full
+        let len = self.workspaces.len();
+        if let Some(idx) = (0..len).map(|i| (self.idx + i) % len).find(|i| !self.workspaces.get(*i).unwrap().is_full()) {
+            return idx;
+        }
+
+        // Else create a new one if we can, else stick with current
+        if DYNAMIC_WORKSPACES {
+            if let Some(idx) = self.create_workspace(conn, screen) {
+                return idx;
+            }
+        }
+        warn!("All workspaces full");
+        return self.idx;
+    }
+
+    pub fn create_workspace(&mut self, conn: &XConn, screen: &Screen) -> Option<usize> {
+        // Limit reached, which is never below the configured count
+        if self.workspaces.len() >= self.max_len() {
+            return None;
+        }
+        return Some(self.create_workspace_unchecked(conn, screen));
+    }
+
+    fn create_workspace_unchecked(&mut self, conn: &XConn, screen: &Screen) -> usize {
+        let idx = self.push_workspace();
+        debug!("Creating workspace: {}", idx);
+
+        // Update EWMH hints with the new count
+        self.publish(conn, screen);
+        return idx;
+    }
+
+    fn push_workspace(&mut self) -> usize {
+        // Dynamic workspaces are named by number
+        let idx = self.workspaces.len();
+        let mut ws = Workspace::default();
+        ws.name = (idx + 1).to_string();
+        self.workspaces.push(ws);
+        return idx;
+    }
+
+    pub fn add_workspace(&mut self, conn: &XConn, screen: &Screen) -> Option<usize> {
+        // On demand, so allowed up to the hard limit whether dynamic or not
+        if self.workspaces.len() >= WORKSPACES_MAX {
+            warn!("Workspace limit reached: {}", WORKSPACES_MAX);
+            return None;
+        }
+
+        // Asked for, so kept even when empty. Count covers every workspace, as any dynamic ones
+        // come before the new one
+        let idx = self.create_workspace_unchecked(conn, screen);
+        self.count = self.workspaces.len();
+        return Some(idx);
+    }
+
+    pub fn remove_workspace(&mut self, conn: &XConn, screen: &Screen) -> bool {
+        // Finish any in-progress animation first, it refers to workspaces by index
+        self.transition_finish(conn, screen);
+
+        // Never the only workspace, nor one with windows on it
+        let idx = self.idx;
+        if self.workspaces.len() < 2 {
+            warn!("Not removing the only workspace");
+            return false;
+        }
+        if self.current().windows.iter().any(|window| !window.sticky) {
+            warn!("Not removing non-empty workspace: {}", idx);
+            return false;
+        }
+        debug!("Removing workspace: {}", idx);
+
+        // Land on the previously active workspace, else the nearest, as long as it isn't shown on another monitor
+        let len = self.workspaces.len();
+        let mut candidates = vec![self.idx_prev];
+        for distance in 1..len {
+            candidates.extend(idx.checked_sub(distance));
+            candidates.push(idx + distance);
+        }
+        let to = match candidates.into_iter().find(|to| *to != idx && *to < len && self.monitor_of(*to).is_none()) {
+            Some(to) => to,
+            None => {
+                warn!("Not removing workspace, every other is shown: {}", idx);
+                return false;
+            },
+        };
+
+        // Sticky windows come along
+        self.move_sticky(idx, to);
+
+        // Unmap and drop it, its minimum going along with it. Never below one though
+        self.workspaces.get_mut(idx).unwrap().deactivate(conn);
+        self.workspaces.remove(idx);
+        if idx < self.count { self.count = (self.count - 1).max(1); }
+
+        // Indices follow the workspaces they refer to
+        self.idx = if to > idx { to - 1 } else { to };
+        self.idx_prev = self.idx;
+        self.forget_workspace(idx);
+
+        // Renumber workspaces still named by their old number, and update windows on those that shifted
+        for (ws_idx, ws) in self.workspaces.iter_mut().enumerate().skip(idx) {
+            if ws.name == (ws_idx + 2).to_string() {
+                ws.name = (ws_idx + 1).to_string();
+            }
+            for window in ws.windows.iter().filter(|window| !window.sticky) {
+                conn.set_wm_desktop(window.xwindow.id, ws_idx);
+            }
+        }
+
+        // Activate where we landed
+        self.workspaces.get_mut(self.idx).unwrap().activate(conn, screen);
+        self.scratchpad.raise(conn);
+
+        // Update EWMH hints
+        self.publish(conn, screen);
+        return true;
+    }
+
+    fn forget_workspace(&mut self, removed: usize) {
+        // Workspaces shown on other monitors follow theirs down. Shown ones are never removed
+        for idx in self.visible.values_mut() {
+            if *idx > removed { *idx -= 1; }
+        }
+    }
+
+    pub fn empty_workspaces(&self) -> Vec<usize> {
+        return self.workspaces.iter().enumerate().filter(|(_, ws)| ws.windows.is_empty()).map(|(idx, _)| idx).collect();
+    }
+
+    pub fn cleanup_empty(&mut self, conn: &XConn, screen: &Screen) {
+        // Only when dynamic, and not mid-transition as that refers to workspaces by index. Cleanup
+        // happens once the transition finishes instead
+        if !DYNAMIC_WORKSPACES || self.transition.is_some() {
+            return;
+        }
+
+        let remove = self.removable_workspaces();
+        if remove.is_empty() {
+            return;
+        }
+
+        for idx in remove.iter() {
+            debug!("Removing empty workspace: {}", idx);
+            self.drop_workspace(*idx);
+        }
+
+        // Renumber dynamic workspaces, and update windows on any that shifted
+        let first = *remove.last().unwrap();
+        for (idx, ws) in self.workspaces.iter_mut().enumerate().skip(self.count) {
+            ws.name = (idx + 1).to_string();
+            if idx >= first {
+                for window in ws.windows.iter().filter(|window| !window.sticky) {
+                    conn.set_wm_desktop(window.xwindow.id, idx);
+                }
+            }
+        }
+
+        // Update EWMH hints
+        self.publish(conn, screen);
+    }
+
+    fn removable_workspaces(&self) -> Vec<usize> {
+        // Empty workspaces above the minimum, other than those shown. Highest first, so removing
+        // doesn't shift the indices still to be removed
+        return self.empty_workspaces().into_iter().rev().filter(|idx| *idx >= self.count && *idx != self.idx && self.monitor_of(*idx).is_none()).collect();
+    }
+
+    fn drop_workspace(&mut self, idx: usize) {
+        self.workspaces.remove(idx);
+
+        // Indices follow the workspaces they refer to
+        if self.idx > idx { self.idx -= 1; }
+        if self.idx_prev == idx { self.idx_prev = self.idx; } else if self.idx_prev > idx { self.idx_prev -= 1; }
+        self.forget_workspace(idx);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.workspaces.len();
+    }
+
+    pub fn count(&self) -> usize {
+        return self.count;
+    }
+
+    pub fn max_len(&self) -> usize {
+        // Most workspaces there can be, room for dynamic workspaces beyond the configured count
+        if DYNAMIC_WORKSPACES {
+            return MAX_WORKSPACES.max(self.count);
+        }
+        return self.count;
+    }
+
+    pub fn index(&self) -> usize {
+        return self.idx;
+    }
+
     pub fn index_next(&self) -> usize {
-        if self.idx < WORKSPACES-1 {
+        if self.idx < self.workspaces.len()-1 {
             return self.idx + 1;
-        } else {
+        } else if WORKSPACES_WRAP {
             return 0;
+        } else {
+            return self.idx;
         }
     }
 
     pub fn index_prev(&self) -> usize {
         if self.idx > 0 {
             return self.idx - 1;
+        } else if WORKSPACES_WRAP {
+            return self.workspaces.len() - 1;
         } else {
-            return WORKSPACES - 1;
+            return self.idx;
         }
     }
 
     pub fn goto(&mut self, conn: &XConn, screen: &Screen, idx: usize) {
+        // Already there or no such workspace, nothing to do (and don't overwrite previous index). Nor if
+        // shown on another monitor, that's focused instead, see WM::goto
+        if idx == self.idx || idx >= self.workspaces.len() || self.monitor_of(idx).is_some() {
+            return;
+        }
+
         // Log
         debug!("Goto workspace: {}", idx);
 
-        // Deactivate current selected workspace
-        self.workspaces.get_mut(self.idx).unwrap().deactivate(conn);
+        // Finish any in-progress animation first
+        self.transition_finish(conn, screen);
 
-        // Update index
+        // Sticky windows come along to the new workspace, so never get unmapped
+        self.move_sticky(self.idx, idx);
+
+        // If animating, start transition. Workspaces get (de)activated on finish
+        let animate = self.animate();
+        if animate {
+            self.transition_start(conn, screen, idx);
+        } else {
+            // Deactivate current selected workspace
+            self.workspaces.get_mut(self.idx).unwrap().deactivate(conn);
+        }
+
+        // Update indices
+        self.idx_prev = self.idx;
         self.idx = idx;
+        conn.set_current_desktop(screen.idx, self.idx);
+        self.publish_layout(conn, screen);
 
         // Activate newly selected workspace
+        if !animate {
+            self.workspaces.get_mut(self.idx).unwrap().activate(conn, screen);
+            self.scratchpad.raise(conn);
+        }
+
+        // Workspace we left may now be removable
+        self.cleanup_empty(conn, screen);
+    }
+
+    fn transition_start(&mut self, conn: &XConn, screen: &Screen, idx: usize) {
+        debug!("Starting workspace transition: {} -> {}", self.idx, idx);
+
+        // Slide in from the right when going up in index, else from the left
+        let direction = if idx > self.idx { 1 } else { -1 };
+
+        // Position incoming windows offscreen, then map
+        for window in self.workspaces.get(idx).unwrap().windows.iter_rev().filter(|window| !window.sticky) {
+            conn.configure_window(window.xwindow.id, &helper::values_configure_move((window.xwindow.x + direction * screen.xwindow.width) as u32, window.xwindow.y as u32));
+            conn.map_window(window.xwindow.id);
+        }
+
+        // Set transition
+        self.transition = Some(Transition {
+            from: self.idx,
+            direction: direction,
+            step: 0,
+            last_step: Instant::now(),
+        });
+    }
+
+    pub fn transition_timeout(&self) -> i32 {
+        // Get milliseconds until next transition step is due, or -1 (no timeout) if none
+        match &self.transition {
+            Some(transition) => return WORKSPACE_ANIMATION_STEP_TIME.checked_sub(transition.last_step.elapsed()).map_or(0, |d| d.as_millis() as i32),
+            None => return -1,
+        }
+    }
+
+    pub fn transition_tick(&mut self, conn: &XConn, screen: &Screen) {
+        // Only if transition in progress and step is due
+        let (from, direction, step) = match &mut self.transition {
+            Some(transition) if transition.last_step.elapsed() >= WORKSPACE_ANIMATION_STEP_TIME => {
+                transition.step += 1;
+                transition.last_step = Instant::now();
+                (transition.from, transition.direction, transition.step)
+            },
+            _ => return,
+        };
+
+        // Last step, finish up
+        if step >= WORKSPACE_ANIMATION_STEPS {
+            self.transition_finish(conn, screen);
+            return;
+        }
+
+        // Calculate current offsets of outgoing and incoming windows
+        let offset_out = -direction * screen.xwindow.width * step / WORKSPACE_ANIMATION_STEPS;
+        let offset_in = offset_out + direction * screen.xwindow.width;
+
+        // Slide the windows
+        for window in self.workspaces.get(from).unwrap().windows.iter() {
+            conn.configure_window(window.xwindow.id, &helper::values_configure_move((window.xwindow.x + offset_out) as u32, window.xwindow.y as u32));
+        }
+        for window in self.workspaces.get(self.idx).unwrap().windows.iter().filter(|window| !window.sticky) {
+            conn.configure_window(window.xwindow.id, &helper::values_configure_move((window.xwindow.x + offset_in) as u32, window.xwindow.y as u32));
+        }
+    }
+
+    pub fn transition_finish(&mut self, conn: &XConn, screen: &Screen) {
+        // Take transition, if any
+        let transition = match self.transition.take() {
+            Some(transition) => transition,
+            None => return,
+        };
+        debug!("Finishing workspace transition");
+
+        // Deactivate outgoing workspace, then put windows back where they belong
+        self.workspaces.get_mut(transition.from).unwrap().deactivate(conn);
+        for window in self.workspaces.get(transition.from).unwrap().windows.iter() {
+            conn.configure_window(window.xwindow.id, &helper::values_configure_move(window.xwindow.x as u32, window.xwindow.y as u32));
+        }
+
+        // Put incoming windows where they belong, then activate
+        for window in self.workspaces.get(self.idx).unwrap().windows.iter() {
+            conn.configure_window(window.xwindow.id, &helper::values_configure_move(window.xwindow.x as u32, window.xwindow.y as u32));
+        }
         self.workspaces.get_mut(self.idx).unwrap().activate(conn, screen);
+        self.scratchpad.raise(conn);
+
+        // Workspace we left may now be removable
+        self.cleanup_empty(conn, screen);
+    }
+
+    pub fn reorder(&mut self, conn: &XConn, screen: &Screen, from: usize, to: usize) {
+        // Nothing to do if same / out of range
+        if from == to || from >= self.workspaces.len() || to >= self.workspaces.len() {
+            return;
+        }
+        debug!("Reordering workspace: {} -> {}", from, to);
+
+        // Finish any in-progress animation first, it refers to workspaces by index
+        self.transition_finish(conn, screen);
+
+        // Swap the workspaces
+        self.workspaces.swap(from, to);
+
+        // Indices follow the workspaces they refer to
+        let follow = |idx: usize| if idx == from { to } else if idx == to { from } else { idx };
+        self.idx = follow(self.idx);
+        self.idx_prev = follow(self.idx_prev);
+        for shown in self.visible.values_mut() {
+            *shown = follow(*shown);
+        }
+
+        // Update EWMH hints
+        self.publish_names(conn, screen);
+        conn.set_current_desktop(screen.idx, self.idx);
+        self.publish_layout(conn, screen);
+        for idx in &[from, to] {
+            for window in self.workspaces.get(*idx).unwrap().windows.iter().filter(|window| !window.sticky) {
+                conn.set_wm_desktop(window.xwindow.id, *idx);
+            }
+        }
+    }
+
+    pub fn move_workspace_left(&mut self, conn: &XConn, screen: &Screen) {
+        // Swap current workspace with the one before it, clamping at the start
+        if self.idx > 0 {
+            self.reorder(conn, screen, self.idx, self.idx - 1);
+        }
+    }
+
+    pub fn move_workspace_right(&mut self, conn: &XConn, screen: &Screen) {
+        // Swap current workspace with the one after it, clamping at the end
+        if self.idx < self.workspaces.len()-1 {
+            self.reorder(conn, screen, self.idx, self.idx + 1);
+        }
+    }
+
+    fn move_sticky(&mut self, from: usize, to: usize) {
+        // Move sticky windows between workspaces, without touching their X state
+        let mut idx = 0;
+        while let Some(window) = self.workspaces.get(from).unwrap().windows.get(idx) {
+            if window.sticky {
+                let window = window.to_owned();
+                self.workspaces.get_mut(from).unwrap().windows.remove(idx);
+                self.workspaces.get_mut(to).unwrap().windows.add_unfocused(window);
+            } else {
+                idx += 1;
+            }
+        }
+    }
+
+    pub fn toggle_sticky(&mut self, conn: &XConn) {
+        let idx = self.idx;
+
+        // Toggle sticky on focused window, if any
+        if let Some(window) = self.current_mut().windows.focused_mut() {
+            debug!("Toggling sticky for window: {}", window.xwindow.id);
+            window.set_sticky(conn, !window.sticky);
+
+            // Sticky windows are on all desktops
+            if window.sticky {
+                conn.set_wm_desktop_all(window.xwindow.id);
+            } else {
+                conn.set_wm_desktop(window.xwindow.id, idx);
+            }
+        }
+    }
+
+    pub fn index_urgent(&self) -> Option<usize> {
+        // First workspace with an urgent window, current workspace first
+        let len = self.workspaces.len();
+        return (0..len).map(|i| (self.idx + i) % len).find(|i| self.workspaces.get(*i).unwrap().is_urgent());
+    }
+
+    pub fn index_prev_active(&self) -> usize {
+        return self.idx_prev;
+    }
+
+    pub fn monitor_of(&self, idx: usize) -> Option<usize> {
+        // Other monitor showing the workspace, if any
+        return self.visible.iter().find(|(_, shown)| **shown == idx).map(|(monitor, _)| *monitor);
+    }
+
+    pub fn monitor_of_window(&self, window_id: XWindowID) -> Option<usize> {
+        return self.workspaces.iter().position(|ws| ws.windows.contains(window_id).is_some()).and_then(|idx| self.monitor_of(idx));
+    }
+
+    pub fn monitor_workspace(&self, monitor: usize) -> Option<usize> {
+        // Workspace shown on another monitor
+        return self.visible.get(&monitor).copied();
+    }
+
+    pub fn visible(&self) -> Vec<(usize, usize)> {
+        // Pairs of monitor and the workspace shown on it, for monitors other than the focused
+        return self.visible.iter().map(|(monitor, idx)| (*monitor, *idx)).collect();
+    }
+
+    pub fn show_on_monitor(&mut self, conn: &XConn, screen: &Screen, monitor: usize) -> bool {
+        // First workspace not yet shown anywhere, else a new one if we can
+        let idx = match self.unshown_workspace() {
+            Some(idx) => idx,
+            None => match self.create_workspace(conn, screen) {
+                Some(idx) => idx,
+                None => return false,
+            },
+        };
+        debug!("Showing workspace {} on monitor {}", idx, monitor);
+
+        // Finish any in-progress animation first, animating only happens with a single monitor
+        self.transition_finish(conn, screen);
+        self.visible.insert(monitor, idx);
+        self.workspaces.get_mut(idx).unwrap().activate(conn, screen);
+        return true;
+    }
+
+    fn unshown_workspace(&self) -> Option<usize> {
+        return (0..self.workspaces.len()).find(|idx| *idx != self.idx && self.monitor_of(*idx).is_none());
+    }
+
+    pub fn hide_monitor(&mut self, conn: &XConn, screen: &Screen, monitor: usize) {
+        // Monitor gone, its workspace goes back to being hidden
+        if let Some(idx) = self.visible.remove(&monitor) {
+            debug!("Hiding workspace {} of monitor {}", idx, monitor);
+            self.workspaces.get_mut(idx).unwrap().deactivate(conn);
+            self.cleanup_empty(conn, screen);
+        }
+    }
+
+    pub fn focus_monitor(&mut self, conn: &XConn, screen: &Screen, from: usize, to: usize) {
+        // Nothing shown there
+        if !self.visible.contains_key(&to) {
+            return;
+        }
+        self.transition_finish(conn, screen);
+
+        // Current workspace stays shown on the monitor we're leaving, the one shown on the other becomes current
+        let idx = self.visible.remove(&to).unwrap();
+        self.visible.insert(from, self.idx);
+
+        // Update indices, nothing to (de)activate as both stay shown
+        self.idx_prev = self.idx;
+        self.idx = idx;
+        conn.set_current_desktop(screen.idx, self.idx);
+        self.publish_layout(conn, screen);
+    }
+
+    fn animate(&self) -> bool {
+        // Sliding would carry windows across other monitors
+        return WORKSPACE_ANIMATION && self.visible.is_empty();
+    }
+
+    pub fn scratchpad_toggle(&mut self, conn: &XConn) {
+        self.scratchpad.toggle(conn);
+
+        // If hidden, hand focus back to the current workspace
+        if !self.scratchpad.visible {
+            if let Some(focused) = self.current().windows.focused() {
+                conn.set_input_focus(focused.xwindow.id);
+            }
+        }
     }
 
     pub fn current(&self) -> &Workspace {
@@ -59,6 +725,53 @@ impl Desktop {
         return self.workspaces.get_mut(idx).unwrap();
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = &Workspace> {
+        return self.workspaces.iter();
+    }
+
+    pub fn find_window_by_id(&self, window_id: XWindowID) -> Option<&Window> {
+        // Look in the scratchpad, then all workspaces
+        if self.scratchpad.contains(window_id) {
+            return self.scratchpad.window.as_ref();
+        }
+        return self.contains(window_id).and_then(|(ws, idx)| ws.windows.get(idx));
+    }
+
+    pub fn find_window_by_id_mut(&mut self, window_id: XWindowID) -> Option<&mut Window> {
+        // Look in the scratchpad, then all workspaces
+        if self.scratchpad.contains(window_id) {
+            return self.scratchpad.window.as_mut();
+        }
+        return self.contains_mut(window_id).and_then(|(ws, idx)| ws.windows.get_mut(idx));
+    }
+
+    pub fn find_draggable_mut(&mut self, window_id: XWindowID) -> Option<&mut Window> {
+        // Only on the current workspace or the scratchpad, else it went away mid-drag
+        if self.scratchpad.contains(window_id) {
+            return self.scratchpad.window.as_mut();
+        }
+        let idx = self.current().windows.contains(window_id)?;
+        return self.current_mut().windows.get_mut(idx);
+    }
+
+    pub fn remove_window(&mut self, conn: &XConn, screen: &Screen, window_id: XWindowID) -> Option<Window> {
+        // Remove from whatever workspace it's in, the layout handles re-focusing
+        if let Some((ws, idx)) = self.contains_mut(window_id) {
+            let window = ws.window_del(conn, screen, idx, window_id);
+
+            // Workspace it was on may now be removable
+            self.cleanup_empty(conn, screen);
+            return Some(window);
+        }
+
+        // Else it may be the scratchpad
+        if self.scratchpad.contains(window_id) {
+            return self.scratchpad.release();
+        }
+
+        return None;
+    }
+
     pub fn contains(&self, window_id: XWindowID) -> Option<(&Workspace, usize)> {
         for ws in self.workspaces.iter() {
             if let Some(idx) = ws.windows.index_of(window_id) {
@@ -77,3 +790,151 @@ impl Desktop {
         return None;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WORKSPACES_MIN;
+
+    #[test]
+    fn next_prev_wrap() {
+        let mut desktop = Desktop::new(3);
+        desktop.idx = 1;
+        assert_eq!((desktop.index_prev(), desktop.index_next()), (0, 2));
+
+        // Past either end wraps around when configured, else stays put
+        desktop.idx = 2;
+        assert_eq!(desktop.index_next(), if WORKSPACES_WRAP { 0 } else { 2 });
+        desktop.idx = 0;
+        assert_eq!(desktop.index_prev(), if WORKSPACES_WRAP { 2 } else { 0 });
+
+        // A single workspace has nowhere else to go
+        let desktop = Desktop::new(1);
+        assert_eq!((desktop.index_prev(), desktop.index_next()), (0, 0));
+    }
+    #[test]
+    fn new_names_and_count() {
+        // Configured names first, numbered after
+        let desktop = Desktop::new(WORKSPACE_NAMES.len() + 2);
+        assert_eq!(desktop.len(), WORKSPACE_NAMES.len() + 2);
+        assert_eq!(desktop.count(), desktop.len());
+        assert_eq!(desktop.workspaces[0].name, WORKSPACE_NAMES[0]);
+        assert_eq!(desktop.workspaces.last().unwrap().name, (WORKSPACE_NAMES.len() + 2).to_string());
+        assert_eq!(desktop.index(), 0);
+    }
+
+    #[test]
+    fn count_clamped() {
+        assert_eq!(helper::workspaces_from_arg("0"), Some(WORKSPACES_MIN));
+        assert_eq!(helper::workspaces_from_arg("4"), Some(4));
+        assert_eq!(Desktop::new(helper::workspaces_from_arg("1000").unwrap()).len(), WORKSPACES_MAX);
+        assert_eq!(helper::workspaces_from_arg("many"), None);
+    }
+    #[test]
+    fn cleanup_reindexes() {
+        // Two dynamic workspaces beyond the minimum, named by number
+        let mut desktop = Desktop::new(2);
+        assert_eq!((desktop.push_workspace(), desktop.push_workspace()), (2, 3));
+        assert_eq!(desktop.workspaces[3].name, "4");
+
+        // Current and the minimum are kept, the rest go highest first
+        desktop.idx = 3;
+        desktop.idx_prev = 2;
+        assert_eq!(desktop.removable_workspaces(), vec![2]);
+        desktop.idx = 1;
+        assert_eq!(desktop.removable_workspaces(), vec![3, 2]);
+
+        // Indices above a dropped workspace shift down, previous falls back to current if dropped
+        desktop.idx = 3;
+        desktop.drop_workspace(2);
+        assert_eq!((desktop.len(), desktop.index(), desktop.index_prev_active()), (3, 2, 2));
+        assert!(desktop.removable_workspaces().is_empty());
+    }
+    #[test]
+    fn monitor_map() {
+        // Shown on another monitor, the first workspace not already shown
+        let mut desktop = Desktop::new(4);
+        desktop.idx = 1;
+        assert_eq!(desktop.unshown_workspace(), Some(0));
+        desktop.visible.insert(1, 0);
+        assert_eq!(desktop.unshown_workspace(), Some(2));
+        desktop.visible.insert(2, 2);
+        assert_eq!((desktop.monitor_of(2), desktop.monitor_workspace(1)), (Some(2), Some(0)));
+        assert_eq!(desktop.unshown_workspace(), Some(3));
+
+        // Removing a workspace below one shown remaps its monitor
+        desktop.idx = 3;
+        desktop.drop_workspace(1);
+        assert_eq!((desktop.monitor_workspace(1), desktop.monitor_workspace(2)), (Some(0), Some(1)));
+
+        // Hidden again, it's up for showing
+        desktop.visible.remove(&1);
+        assert_eq!(desktop.monitor_of(0), None);
+        assert_eq!(desktop.unshown_workspace(), Some(0));
+    }
+
+    #[test]
+    fn sticky_windows_move() {
+        let mut desktop = Desktop::new(2);
+        for (id, sticky) in [(1, true), (2, false), (3, true)].iter() {
+            let mut window = Window::from(*id);
+            window.sticky = *sticky;
+            desktop.workspaces[0].windows.add(window);
+        }
+        desktop.workspaces[1].windows.add(Window::from(4));
+
+        // Only sticky windows come along, without taking focus
+        desktop.move_sticky(0, 1);
+        let ids = |ws: &Workspace| ws.windows.iter().map(|window| window.xwindow.id).collect::<Vec<XWindowID>>();
+        assert_eq!(ids(&desktop.workspaces[0]), vec![2]);
+        assert_eq!(ids(&desktop.workspaces[1]), vec![1, 3, 4]);
+        assert!(desktop.workspaces[1].windows.is_focused(4));
+    }
+
+    #[test]
+    fn urgent_from_current() {
+        let mut desktop = Desktop::new(4);
+        assert_eq!(desktop.index_urgent(), None);
+        for idx in [0, 2].iter() {
+            let mut window = Window::from(*idx as XWindowID + 1);
+            window.urgent = true;
+            desktop.workspaces[*idx].windows.add(window);
+        }
+
+        // Searching onwards from the current workspace, wrapping around
+        assert_eq!(desktop.index_urgent(), Some(0));
+        desktop.idx = 1;
+        assert_eq!(desktop.index_urgent(), Some(2));
+        desktop.idx = 3;
+        assert_eq!(desktop.index_urgent(), Some(0));
+    }
+
+    #[test]
+    fn find_window_anywhere() {
+        let mut desktop = Desktop::new(2);
+        desktop.workspaces[1].windows.add(Window::from(1));
+        desktop.scratchpad.window = Some(Window::from(2));
+
+        // In any workspace, or the scratchpad
+        assert_eq!(desktop.find_window_by_id(1).map(|window| window.xwindow.id), Some(1));
+        assert_eq!(desktop.find_window_by_id(2).map(|window| window.xwindow.id), Some(2));
+        assert!(desktop.find_window_by_id(3).is_none());
+
+        desktop.find_window_by_id_mut(1).unwrap().floating = true;
+        assert!(desktop.workspaces[1].windows.get(0).unwrap().floating);
+    }
+
+    #[test]
+    fn draggable_only_when_shown() {
+        let mut desktop = Desktop::new(2);
+        desktop.workspaces[0].windows.add(Window::from(1));
+        desktop.workspaces[1].windows.add(Window::from(2));
+        desktop.scratchpad.window = Some(Window::from(3));
+        assert!(desktop.find_draggable_mut(1).is_some());
+        assert!(desktop.find_draggable_mut(3).is_some());
+
+        // Moved off the current workspace, or closed, ends the drag
+        assert!(desktop.find_draggable_mut(2).is_none());
+        assert!(desktop.find_draggable_mut(4).is_none());
+    }
+}
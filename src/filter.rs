@@ -0,0 +1,119 @@
+use crate::config::WINDOW_FILTERS;
+use crate::x::{XConn, XWindowID};
+
+// What to do with a window about to be mapped
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FilterAction {
+    // Track in a workspace as usual
+    Manage,
+
+    // Map, but leave alone (e.g. docks)
+    Ignore,
+
+    // Track, but floating regardless of layout. Centered over the parent window if given, else the screen
+    Float(Option<XWindowID>),
+}
+
+// Rule deciding how a window gets mapped, see WINDOW_FILTERS in config
+pub trait WindowFilter {
+    fn accept(&self, conn: &XConn, window_id: XWindowID) -> FilterAction;
+}
+
+pub struct FilterChain {
+    filters: &'static [&'static dyn WindowFilter],
+}
+
+impl FilterChain {
+    pub fn from_config() -> Self {
+        Self {
+            filters: WINDOW_FILTERS,
+        }
+    }
+
+    pub fn apply(&self, conn: &XConn, window_id: XWindowID) -> FilterAction {
+        // Lazily, so filters after one ignoring the window aren't asked
+        return resolve(self.filters.iter().map(|filter| filter.accept(conn, window_id)));
+    }
+}
+
+fn resolve<I: IntoIterator<Item = FilterAction>>(actions: I) -> FilterAction {
+    // Each filter in order, first to ignore wins. Else float if any said to, over a parent if any gave one
+    let mut action = FilterAction::Manage;
+    for next in actions {
+        match next {
+            FilterAction::Ignore => return FilterAction::Ignore,
+            FilterAction::Float(Some(parent_id)) => action = FilterAction::Float(Some(parent_id)),
+            FilterAction::Float(None) if action == FilterAction::Manage => action = FilterAction::Float(None),
+            _ => {},
+        }
+    }
+    return action;
+}
+
+// Override-redirect windows (menus, tooltips) manage themselves. These never send a map
+// request, but may turn up when adopting existing windows
+pub struct OverrideRedirect;
+
+impl WindowFilter for OverrideRedirect {
+    fn accept(&self, conn: &XConn, window_id: XWindowID) -> FilterAction {
+        match conn.get_window_attributes(window_id) {
+            Some(attr) if attr.override_redirect() => return FilterAction::Ignore,
+            _ => return FilterAction::Manage,
+        }
+    }
+}
+
+// By _NET_WM_WINDOW_TYPE. Dialogs / utilities / splash screens float, anything else that isn't
+// normal (docks, desktops, notifications...) is shown but left alone. No type at all is normal
+pub struct WindowType;
+
+impl WindowFilter for WindowType {
+    fn accept(&self, conn: &XConn, window_id: XWindowID) -> FilterAction {
+        let window_types = conn.get_wm_window_type(window_id).unwrap_or_default();
+        if window_types.is_empty() {
+            return FilterAction::Manage;
+        }
+
+        let floating = [conn.atoms.WM_WINDOW_TYPE_DIALOG, conn.atoms.WM_WINDOW_TYPE_UTILITY, conn.atoms.WM_WINDOW_TYPE_SPLASH];
+        let managed = [conn.atoms.WM_WINDOW_TYPE_NORMAL, conn.atoms.WM_WINDOW_TYPE_TOOLBAR];
+        if window_types.iter().any(|window_type| floating.contains(window_type)) {
+            return FilterAction::Float(None);
+        }
+        if window_types.iter().any(|window_type| managed.contains(window_type)) {
+            return FilterAction::Manage;
+        }
+        return FilterAction::Ignore;
+    }
+}
+
+// Transients (dialogs) float, centered over their parent
+pub struct TransientFor;
+
+impl WindowFilter for TransientFor {
+    fn accept(&self, conn: &XConn, window_id: XWindowID) -> FilterAction {
+        match conn.get_transient_for(window_id) {
+            Some(parent_id) => return FilterAction::Float(Some(parent_id)),
+            None => return FilterAction::Manage,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_precedence() {
+        use FilterAction::*;
+        assert_eq!(resolve(vec![]), Manage);
+        assert_eq!(resolve(vec![Manage, Float(None), Manage]), Float(None));
+
+        // A parent to center over beats floating without one, whichever comes first
+        assert_eq!(resolve(vec![Float(Some(7)), Float(None)]), Float(Some(7)));
+        assert_eq!(resolve(vec![Float(None), Float(Some(7))]), Float(Some(7)));
+
+        // Ignoring wins outright
+        assert_eq!(resolve(vec![Float(Some(7)), Ignore]), Ignore);
+        assert_eq!(resolve(vec![Ignore, Float(Some(7))]), Ignore);
+    }
+}
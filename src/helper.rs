@@ -1,45 +1,148 @@
+use crate::config::{MODKEY, WORKSPACES_MAX, WORKSPACES_MIN};
+
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
+// Environment variable set when re-executing ourselves to restart in place
+pub const RESTART_ENV: &str = "AFWM_RESTARTED";
+
 // Root window mouse button event mask
 pub const ROOT_BUTTON_GRAB_MASK: xcb::ButtonMask = xcb::EVENT_MASK_BUTTON_PRESS|xcb::EVENT_MASK_BUTTON_RELEASE;
 
 // Root window pointer event mask
 pub const ROOT_POINTER_GRAB_MASK: xcb::EventMask = xcb::EVENT_MASK_BUTTON_RELEASE|xcb::EVENT_MASK_BUTTON_MOTION;
 
+// Lock modifiers (CapsLock, NumLock) that shouldn't affect keybinds
+pub const LOCK_MASK: xcb::ModMask = xcb::MOD_MASK_LOCK|xcb::MOD_MASK_2;
+
+// Lock modifier combinations to grab keybinds with, so they work regardless of lock state
+pub const LOCK_MASK_COMBINATIONS: [xcb::ModMask; 4] = [0, xcb::MOD_MASK_LOCK, xcb::MOD_MASK_2, xcb::MOD_MASK_LOCK|xcb::MOD_MASK_2];
+
+// All keyboard modifiers, i.e. excluding mouse button state
+pub const MODIFIER_MASK: xcb::ModMask = xcb::MOD_MASK_SHIFT|xcb::MOD_MASK_LOCK|xcb::MOD_MASK_CONTROL|xcb::MOD_MASK_1|xcb::MOD_MASK_2|xcb::MOD_MASK_3|xcb::MOD_MASK_4|xcb::MOD_MASK_5;
+
+// Strip lock modifiers and mouse button state from an event's modifier state
+pub fn clean_mask(state: u16) -> xcb::ModMask {
+    return state as u32 & MODIFIER_MASK & !LOCK_MASK;
+}
+
+// Modifier mask by name, for choosing the modkey at runtime
+pub fn modkey_from_name(name: &str) -> Option<xcb::ModMask> {
+    match name.to_lowercase().as_str() {
+        "mod1"|"alt"   => return Some(xcb::MOD_MASK_1),
+        "mod2"         => return Some(xcb::MOD_MASK_2),
+        "mod3"         => return Some(xcb::MOD_MASK_3),
+        "mod4"|"super" => return Some(xcb::MOD_MASK_4),
+        "mod5"         => return Some(xcb::MOD_MASK_5),
+        _              => return None,
+    }
+}
+
+// Workspace count from an argument, clamped to a sane range
+pub fn workspaces_from_arg(arg: &str) -> Option<usize> {
+    return arg.parse::<usize>().ok().map(|count| count.max(WORKSPACES_MIN).min(WORKSPACES_MAX));
+}
+
+// Swap the configured MODKEY in a bind's mask for the modkey chosen at runtime
+pub fn resolve_modkey(mask: xcb::ModMask, modkey: xcb::ModMask) -> xcb::ModMask {
+    if mask & MODKEY == 0 {
+        return mask;
+    }
+    return (mask & !MODKEY) | modkey;
+}
+
+// Find the bind matching a key press, for key binds as well as chords
+pub fn find_bind<T>(binds: &[(xcb::ModMask, xcb::Keysym, T)], modkey: xcb::ModMask, press_mask: xcb::ModMask, press_key: xcb::Keysym) -> Option<&T> {
+    return binds.iter().find(|(mask, key, _)| resolve_modkey(*mask, modkey) == press_mask && *key == press_key).map(|(_, _, bind)| bind);
+}
+
+// Convert opacity fraction to _NET_WM_WINDOW_OPACITY cardinal
+pub fn opacity_to_cardinal(opacity: f32) -> u32 {
+    // _NET_WM_WINDOW_OPACITY is a fraction of 0xffffffff (opaque)
+    return (opacity.max(0.0).min(1.0) as f64 * u32::MAX as f64).round() as u32;
+}
+
+// Soonest of two poll timeouts in milliseconds, where -1 means no timeout
+pub fn min_timeout(a: i32, b: i32) -> i32 {
+    if a < 0 {
+        return b;
+    } else if b < 0 {
+        return a;
+    }
+    return a.min(b);
+}
+
+// Path of a file in the runtime dir, named per display so multiple instances don't clash
+pub fn runtime_path(extension: &str) -> PathBuf {
+    let dir = env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| "/tmp".to_owned());
+    let display = env::var("DISPLAY").unwrap_or_default();
+    return PathBuf::from(dir).join(format!("afwm{}.{}", display, extension));
+}
+
+// Command re-executing ourselves in place with the same arguments, marked as a restart
+pub fn restart_command<I: IntoIterator<Item = String>>(exe: PathBuf, args: I) -> Command {
+    let mut command = Command::new(exe);
+    command.args(args).env(RESTART_ENV, "1");
+    return command;
+}
+
 // Values array of configurations setting window position
 pub fn values_configure_move(x: u32, y: u32) -> [(u16, u32); 2] {
     debug!("VALUES: configure move");
     return [(xcb::CONFIG_WINDOW_X as u16, x), (xcb::CONFIG_WINDOW_Y as u16, y)];
 }
 
-// Values array of configurations setting window size
-pub fn values_configure_resize(width: u32, height: u32) -> [(u16, u32); 2] {
-    debug!("VALUES: configure resize");
-    return [(xcb::CONFIG_WINDOW_WIDTH as u16, width), (xcb::CONFIG_WINDOW_HEIGHT as u16, height)];
-}
-
 // Values array of configurations setting window geometry
 pub fn values_configure_geometry(x: u32, y: u32, width: u32, height: u32) -> [(u16, u32); 4] {
     debug!("VALUES: configure geometry");
     return [ (xcb::CONFIG_WINDOW_X as u16, x), (xcb::CONFIG_WINDOW_Y as u16, y), (xcb::CONFIG_WINDOW_WIDTH as u16, width), (xcb::CONFIG_WINDOW_HEIGHT as u16, height) ];
 }
 
+// Values array of configuration setting window border width
+pub fn values_configure_border_width(width: u32) -> [(u16, u32); 1] {
+    debug!("VALUES: configure border width");
+    return [(xcb::CONFIG_WINDOW_BORDER_WIDTH as u16, width)];
+}
+
 // Values array of configuration setting window ontop of stack
 pub fn values_configure_stack_above() -> [(u16, u32); 1] {
     debug!("VALUES: configure stack above");
     return [(xcb::CONFIG_WINDOW_STACK_MODE as u16, xcb::STACK_MODE_ABOVE)];
 }
 
+// Values array of configurations setting window directly below a sibling in the stack
+pub fn values_configure_stack_below(sibling_id: u32) -> [(u16, u32); 2] {
+    debug!("VALUES: configure stack below");
+    return [(xcb::CONFIG_WINDOW_SIBLING as u16, sibling_id), (xcb::CONFIG_WINDOW_STACK_MODE as u16, xcb::STACK_MODE_BELOW)];
+}
+
 // Values array of attributes setting the cursor to use
 pub fn values_attributes_cursor(cursor_id: u32) -> [(u32, u32); 1] {
     debug!("VALUES: attributes cursor");
     return [(xcb::CW_CURSOR, cursor_id)];
 }
 
+// Values array of attributes setting the border color
+pub fn values_attributes_border_color(color: u32) -> [(u32, u32); 1] {
+    debug!("VALUES: attributes border color");
+    return [(xcb::CW_BORDER_PIXEL, color)];
+}
+
+// Values array of attributes setting override redirect (i.e. we don't manage it)
+pub fn values_attributes_override_redirect() -> [(u32, u32); 1] {
+    debug!("VALUES: attributes override redirect");
+    return [(xcb::CW_OVERRIDE_REDIRECT, 1)];
+}
+
 // Values array of attributes setting root window event mask
 pub fn values_attributes_root() -> [(u32, u32); 1] {
     debug!("VALUES: attributes root");
     return [(xcb::CW_EVENT_MASK,
         xcb::EVENT_MASK_SUBSTRUCTURE_REDIRECT| // -> Child window CirculateRequest, ConfigureRequest, MapRequest events
-        xcb::EVENT_MASK_STRUCTURE_NOTIFY       // -> Self CirculateNotify, ConfigureNotify, DestroyNotify, GravityNotify, MapNotify, ReparentNotify, UnmapNotify events
+        xcb::EVENT_MASK_STRUCTURE_NOTIFY|      // -> Self CirculateNotify, ConfigureNotify, DestroyNotify, GravityNotify, MapNotify, ReparentNotify, UnmapNotify events
+        xcb::EVENT_MASK_KEY_RELEASE            // -> Self KeyRelease events, only while root has focus. Releasing a modifier
+                                               //    is only reliably seen with the keyboard grabbed, see WM::mru_cycle
     )];
 }
 
@@ -47,8 +150,11 @@ pub fn values_attributes_root() -> [(u32, u32); 1] {
 pub fn values_attributes_child_events() -> [(u32, u32); 1] {
     debug!("VALUES: attributes child events");
     return [(xcb::CW_EVENT_MASK,
-        xcb::EVENT_MASK_ENTER_WINDOW|    // -> Self EnterNotify events
-        xcb::EVENT_MASK_STRUCTURE_NOTIFY // -> Self CirculateNotify, ConfigureNotify, DestroyNotify, GravityNotify, MapNotify, ReparentNotify, UnmapNotify events
+        xcb::EVENT_MASK_ENTER_WINDOW|     // -> Self EnterNotify events
+        xcb::EVENT_MASK_EXPOSURE|         // -> Self Expose events
+        xcb::EVENT_MASK_FOCUS_CHANGE|     // -> Self FocusIn, FocusOut events
+        xcb::EVENT_MASK_PROPERTY_CHANGE|  // -> Self PropertyNotify events
+        xcb::EVENT_MASK_STRUCTURE_NOTIFY  // -> Self CirculateNotify, ConfigureNotify, DestroyNotify, GravityNotify, MapNotify, ReparentNotify, UnmapNotify events
     )];
 }
 
@@ -56,4 +162,100 @@ pub fn values_attributes_child_events() -> [(u32, u32); 1] {
 pub fn values_attributes_no_events() -> [(u32, u32); 1] {
     debug!("VALUES: attributes no events");
     return [(xcb::CW_EVENT_MASK, xcb::EVENT_MASK_NO_EVENT)];
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x11::keysym;
+
+    use std::ffi::OsStr;
+
+    #[test]
+    fn clean_mask_strips_locks_and_buttons() {
+        let state = xcb::MOD_MASK_SHIFT|xcb::MOD_MASK_LOCK|xcb::MOD_MASK_2|xcb::MOD_MASK_4|xcb::KEY_BUT_MASK_BUTTON_1;
+        assert_eq!(clean_mask(state as u16), xcb::MOD_MASK_SHIFT|xcb::MOD_MASK_4);
+        assert_eq!(clean_mask(LOCK_MASK as u16), 0);
+    }
+
+    #[test]
+    fn opacity_to_cardinal_clamped() {
+        assert_eq!(opacity_to_cardinal(1.0), u32::MAX);
+        assert_eq!(opacity_to_cardinal(0.0), 0);
+        assert_eq!(opacity_to_cardinal(0.5), 0x80000000);
+
+        // Out of range is opaque / transparent
+        assert_eq!(opacity_to_cardinal(2.0), u32::MAX);
+        assert_eq!(opacity_to_cardinal(-1.0), 0);
+    }
+
+    #[test]
+    fn lock_combinations_cover_locks() {
+        // Every combination of the lock modifiers and nothing else, once each
+        let mut seen = Vec::new();
+        for lock_mask in LOCK_MASK_COMBINATIONS.iter() {
+            assert_eq!(lock_mask & !LOCK_MASK, 0);
+            assert!(!seen.contains(lock_mask));
+            seen.push(*lock_mask);
+
+            // Pressed with any of them, the bind still matches
+            assert_eq!(clean_mask((xcb::MOD_MASK_4|lock_mask) as u16), xcb::MOD_MASK_4);
+        }
+        assert_eq!(seen.len(), 1 << LOCK_MASK.count_ones());
+    }
+
+    #[test]
+    fn find_bind_chord() {
+        // Prefix bound with the modkey, following keys without
+        let chords: &[(xcb::ModMask, xcb::Keysym, &[(xcb::ModMask, xcb::Keysym, u8)])] = &[
+            (MODKEY, keysym::XK_w, &[(0, keysym::XK_f, 1), (0, keysym::XK_t, 2)]),
+        ];
+        assert!(find_bind(chords, MODKEY, 0, keysym::XK_w).is_none());
+        let chord = find_bind(chords, MODKEY, MODKEY, keysym::XK_w).unwrap();
+
+        // Unknown or modified keys don't complete it
+        assert_eq!(find_bind(chord, MODKEY, 0, keysym::XK_t), Some(&2));
+        assert_eq!(find_bind(chord, MODKEY, 0, keysym::XK_x), None);
+        assert_eq!(find_bind(chord, MODKEY, xcb::MOD_MASK_SHIFT, keysym::XK_f), None);
+    }
+
+    #[test]
+    fn restart_keeps_arguments() {
+        let command = restart_command(PathBuf::from("/usr/bin/afwm"), vec!["-m".to_owned(), "alt".to_owned()]);
+        assert_eq!(command.get_program(), "/usr/bin/afwm");
+        assert_eq!(command.get_args().collect::<Vec<_>>(), vec!["-m", "alt"]);
+
+        // Marked, so startup commands aren't run again
+        assert_eq!(command.get_envs().collect::<Vec<_>>(), vec![(OsStr::new(RESTART_ENV), Some(OsStr::new("1")))]);
+    }
+
+    #[test]
+    fn child_event_mask() {
+        let [(attribute, mask)] = values_attributes_child_events();
+        assert_eq!(attribute, xcb::CW_EVENT_MASK);
+        for event in [xcb::EVENT_MASK_ENTER_WINDOW, xcb::EVENT_MASK_EXPOSURE, xcb::EVENT_MASK_FOCUS_CHANGE, xcb::EVENT_MASK_PROPERTY_CHANGE, xcb::EVENT_MASK_STRUCTURE_NOTIFY].iter() {
+            assert_ne!(mask & event, 0, "missing {:#x}", event);
+        }
+
+        // Redirecting is only for the root window, and nothing once unmanaged
+        assert_eq!(mask & xcb::EVENT_MASK_SUBSTRUCTURE_REDIRECT, 0);
+        assert_eq!(values_attributes_no_events(), [(xcb::CW_EVENT_MASK, xcb::EVENT_MASK_NO_EVENT)]);
+    }
+
+    #[test]
+    fn hidden_unmap_unseen() {
+        // XConn::hide_window unmaps with no events selected on the window, and the root must not report
+        // it either, else hidden windows would be untracked on their UnmapNotify
+        let [(_, none)] = values_attributes_no_events();
+        let [(_, root)] = values_attributes_root();
+        assert_eq!(none & xcb::EVENT_MASK_STRUCTURE_NOTIFY, 0);
+        assert_eq!(root & xcb::EVENT_MASK_SUBSTRUCTURE_NOTIFY, 0);
+    }
+
+    #[test]
+    fn root_event_mask() {
+        let [(attribute, mask)] = values_attributes_root();
+        assert_eq!(attribute, xcb::CW_EVENT_MASK);
+        assert_eq!(mask, xcb::EVENT_MASK_SUBSTRUCTURE_REDIRECT|xcb::EVENT_MASK_STRUCTURE_NOTIFY|xcb::EVENT_MASK_KEY_RELEASE);
+    }
+}
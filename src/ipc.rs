@@ -0,0 +1,339 @@
+use crate::desktop::Desktop;
+use crate::helper;
+use crate::x::XWindowID;
+
+use std::fs;
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+
+// Most input buffered from a client without a complete command line, past which it's dropped
+const CLIENT_BUFFER_MAX: usize = 4096;
+
+// Commands changing state, handed back for the window manager to carry out
+pub enum Action {
+    // Rename the current workspace
+    Rename(String),
+}
+
+// Snapshot of state reported to subscribers, compared to detect changes
+#[derive(Clone, Default, PartialEq)]
+struct State {
+    workspace: usize,
+    layout: String,
+    focused: Option<XWindowID>,
+    title: String,
+    workspaces: Vec<(String, usize)>,
+}
+
+impl State {
+    fn new(desktop: &Desktop) -> Self {
+        let focused = desktop.current().windows.focused();
+        Self {
+            workspace: desktop.index(),
+            layout: desktop.current().layout.name().to_owned(),
+            focused: focused.map(|window| window.xwindow.id),
+            title: focused.map_or(String::new(), |window| window.title.clone()),
+            workspaces: desktop.iter().map(|ws| (ws.name.clone(), ws.windows.len())).collect(),
+        }
+    }
+
+    fn to_json(&self, event: &str) -> String {
+        // Per-workspace occupancy
+        let workspaces: Vec<String> = self.workspaces.iter()
+            .map(|(name, windows)| format!("{{\"name\":\"{}\",\"windows\":{}}}", json_escape(name), windows))
+            .collect();
+
+        return format!(
+            "{{\"event\":\"{}\",\"workspace\":{},\"layout\":\"{}\",\"focused\":{},\"title\":\"{}\",\"workspaces\":[{}]}}\n",
+            event,
+            self.workspace,
+            self.layout,
+            self.focused.map_or("null".to_owned(), |id| id.to_string()),
+            json_escape(&self.title),
+            workspaces.join(","),
+        );
+    }
+}
+
+fn tree_json(desktop: &Desktop) -> String {
+    // Every workspace with its windows front to back, and what's current / focused
+    let workspaces: Vec<String> = desktop.iter().map(|ws| {
+        let windows: Vec<String> = ws.windows.iter().map(|window| {
+            let xwindow = &window.xwindow;
+            return format!(
+                "{{\"id\":{},\"title\":\"{}\",\"class\":\"{}\",\"x\":{},\"y\":{},\"width\":{},\"height\":{},\"floating\":{},\"sticky\":{},\"fullscreen\":{}}}",
+                xwindow.id,
+                json_escape(&window.title),
+                json_escape(&window.class),
+                xwindow.x,
+                xwindow.y,
+                xwindow.width,
+                xwindow.height,
+                window.floating,
+                window.sticky,
+                window.fullscreen,
+            );
+        }).collect();
+
+        return format!(
+            "{{\"name\":\"{}\",\"layout\":\"{}\",\"focused\":{},\"windows\":[{}]}}",
+            json_escape(&ws.name),
+            ws.layout.name(),
+            ws.windows.focused().map_or("null".to_owned(), |window| window.xwindow.id.to_string()),
+            windows.join(","),
+        );
+    }).collect();
+
+    return format!(
+        "{{\"workspace\":{},\"focused\":{},\"workspaces\":[{}]}}\n",
+        desktop.index(),
+        desktop.current().windows.focused().map_or("null".to_owned(), |window| window.xwindow.id.to_string()),
+        workspaces.join(","),
+    );
+}
+
+fn json_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    return escaped;
+}
+
+pub struct Ipc {
+    // Socket path, removed on drop
+    path: PathBuf,
+    listener: UnixListener,
+
+    // Connected clients awaiting a command, with buffered input
+    clients: Vec<(UnixStream, String)>,
+
+    // Clients subscribed to state change events
+    subscribers: Vec<UnixStream>,
+
+    // Last state sent to subscribers
+    state: State,
+}
+
+impl Ipc {
+    pub fn bind() -> Option<Self> {
+        // Socket lives in runtime dir
+        let path = helper::runtime_path("sock");
+
+        // Remove any stale socket from a previous run
+        let _ = fs::remove_file(&path);
+
+        // Bind, non-blocking so we never hold up the event loop
+        let listener = match UnixListener::bind(&path) {
+            Ok(listener) => listener,
+            Err(err) => {
+                warn!("Binding IPC socket {:?}: {}", path, err);
+                return None;
+            },
+        };
+        listener.set_nonblocking(true).expect("Setting IPC socket non-blocking");
+        debug!("Bound IPC socket: {:?}", path);
+
+        return Some(Self {
+            path: path,
+            listener: listener,
+            clients: Vec::new(),
+            subscribers: Vec::new(),
+            state: State::default(),
+        });
+    }
+
+    pub fn fds(&self) -> Vec<RawFd> {
+        // Listener and clients we're reading commands from
+        let mut fds = vec![self.listener.as_raw_fd()];
+        fds.extend(self.clients.iter().map(|(stream, _)| stream.as_raw_fd()));
+        return fds;
+    }
+
+    pub fn handle(&mut self, desktop: &Desktop) -> Vec<Action> {
+        let mut actions = Vec::new();
+
+        // Accept any new clients
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    debug!("IPC client connected");
+                    if stream.set_nonblocking(true).is_ok() {
+                        self.clients.push((stream, String::new()));
+                    }
+                },
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    warn!("Accepting IPC client: {}", err);
+                    break;
+                },
+            }
+        }
+
+        // Read from clients, handling complete command lines
+        let mut idx = 0;
+        while idx < self.clients.len() {
+            let (stream, buf) = self.clients.get_mut(idx).unwrap();
+
+            // Read what's available, dropping the client on EOF / error, or if it never ends a line
+            let mut bytes = [0u8; 256];
+            let keep = match stream.read(&mut bytes) {
+                Ok(0) => false,
+                Ok(len) if buf.len() + len > CLIENT_BUFFER_MAX => {
+                    warn!("IPC client sent over {} bytes without a command", CLIENT_BUFFER_MAX);
+                    false
+                },
+                Ok(len) => { buf.push_str(&String::from_utf8_lossy(&bytes[..len])); true },
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock => true,
+                Err(_) => false,
+            };
+            if !keep {
+                debug!("IPC client disconnected");
+                self.clients.remove(idx);
+                continue;
+            }
+
+            // Take every complete line, leaving any partial one buffered for the next read
+            let mut commands = Vec::new();
+            while let Some(end) = buf.find('\n') {
+                commands.push(buf.drain(..=end).collect::<String>().trim().to_owned());
+            }
+
+            // Handle in order, stopping once subscribed as the client no longer sends commands
+            let mut subscribed = false;
+            for command in commands {
+                if self.command(idx, &command, desktop, &mut actions) {
+                    subscribed = true;
+                    break;
+                }
+            }
+            if !subscribed {
+                idx += 1;
+            }
+        }
+
+        return actions;
+    }
+
+    fn command(&mut self, idx: usize, command: &str, desktop: &Desktop, actions: &mut Vec<Action>) -> bool {
+        debug!("IPC command: {}", command);
+        let (stream, _) = self.clients.get_mut(idx).unwrap();
+
+        // Commands with an argument
+        if let Some(name) = command.strip_prefix("rename ") {
+            actions.push(Action::Rename(name.trim().to_owned()));
+            let _ = stream.write_all(b"{\"ok\":true}\n");
+            return false;
+        }
+
+        match command {
+            // Turn connection into event stream, starting with current state
+            "subscribe" => {
+                // Existing subscribers get any change not yet sent first, it'd be lost once the state moves on
+                self.update(desktop);
+
+                let (mut stream, _) = self.clients.remove(idx);
+                let state = State::new(desktop);
+                if stream.write_all(state.to_json("subscribe").as_bytes()).is_ok() {
+                    self.subscribers.push(stream);
+                }
+                self.state = state;
+                return true;
+            },
+
+            // Dump all workspaces and windows, for debugging / scripting
+            "tree" => {
+                let _ = stream.write_all(tree_json(desktop).as_bytes());
+            },
+
+            _ => {
+                let _ = stream.write_all(b"{\"error\":\"unknown command\"}\n");
+            },
+        }
+        return false;
+    }
+
+    pub fn update(&mut self, desktop: &Desktop) {
+        // No point working out state with no-one listening
+        if self.subscribers.is_empty() {
+            return;
+        }
+
+        // Only send on change
+        let state = State::new(desktop);
+        if state == self.state {
+            return;
+        }
+
+        // Name each kind of change
+        let mut events = Vec::new();
+        if state.workspace != self.state.workspace { events.push("workspace"); }
+        if state.workspaces != self.state.workspaces { events.push("window"); }
+        if state.layout != self.state.layout { events.push("layout"); }
+        if state.focused != self.state.focused || state.title != self.state.title { events.push("focus"); }
+
+        for event in events {
+            self.broadcast(&state.to_json(event));
+        }
+        self.state = state;
+    }
+
+    fn broadcast(&mut self, line: &str) {
+        // Write to subscribers, dropping any that disconnected or can't keep up
+        self.subscribers.retain(|stream| {
+            let mut stream = stream;
+            return stream.write_all(line.as_bytes()).is_ok();
+        });
+    }
+}
+
+impl Drop for Ipc {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::windows::Window;
+
+    #[test]
+    fn json_escape_special() {
+        assert_eq!(json_escape("plain"), "plain");
+        assert_eq!(json_escape("say \"hi\"\\"), "say \\\"hi\\\"\\\\");
+        assert_eq!(json_escape("a\nb\tc\r"), "a\\nb\\tc\\r");
+        assert_eq!(json_escape("\u{1}ü"), "\\u0001ü");
+    }
+
+    #[test]
+    fn tree_json_workspaces() {
+        let mut desktop = Desktop::new(2);
+        let mut window = Window::from(7);
+        window.title = "a \"b\"".to_owned();
+        window.class = "term".to_owned();
+        window.xwindow.width = 100;
+        window.xwindow.height = 50;
+        window.floating = true;
+        desktop.get_mut(0).windows.add(window);
+
+        assert_eq!(tree_json(&desktop), concat!(
+            "{\"workspace\":0,\"focused\":7,\"workspaces\":[",
+            "{\"name\":\"1\",\"layout\":\"floating\",\"focused\":7,\"windows\":[",
+            "{\"id\":7,\"title\":\"a \\\"b\\\"\",\"class\":\"term\",\"x\":0,\"y\":0,\"width\":100,\"height\":50,\"floating\":true,\"sticky\":false,\"fullscreen\":false}",
+            "]},",
+            "{\"name\":\"2\",\"layout\":\"floating\",\"focused\":null,\"windows\":[]}",
+            "]}\n",
+        ));
+    }
+}
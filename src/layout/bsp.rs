@@ -0,0 +1,413 @@
+use crate::config::{AUTO_FULLSCREEN_SINGLE, BSP_RATIO_MAX, BSP_RATIO_MIN, THEME};
+use crate::helper;
+use crate::layout::{floating, tiling};
+use crate::screen::Screen;
+use crate::windows::{Geometry, Window};
+use crate::workspace::Workspace;
+use crate::x::{XConn, XWindowID};
+
+use std::collections::HashMap;
+
+// Which way a split divides its area
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SplitDir {
+    // Side by side, left and right
+    Horizontal,
+
+    // Stacked, left on top
+    Vertical,
+}
+
+impl SplitDir {
+    fn flip(self) -> Self {
+        match self {
+            SplitDir::Horizontal => return SplitDir::Vertical,
+            SplitDir::Vertical => return SplitDir::Horizontal,
+        }
+    }
+}
+
+// Tiled windows as a binary tree, each split dividing its area between two subtrees with
+// the left getting ratio of it
+#[derive(Clone, Debug)]
+pub enum BspTree {
+    Leaf(XWindowID),
+    Split {
+        dir: SplitDir,
+        ratio: f32,
+        left: Box<BspTree>,
+        right: Box<BspTree>,
+    },
+}
+
+impl BspTree {
+    pub fn insert(self, window_id: XWindowID) -> BspTree {
+        // Split the largest area, the later one if tied so windows fill in order
+        let target = self.leaves_with_area(1.0).into_iter().fold(None, |best: Option<(XWindowID, f32)>, (id, area)| match best {
+            Some((_, best_area)) if best_area > area => best,
+            _ => Some((id, area)),
+        });
+        return match target {
+            Some((target, _)) => self.split_leaf(target, window_id, SplitDir::Horizontal),
+            None => BspTree::Leaf(window_id),
+        };
+    }
+
+    fn split_leaf(self, target: XWindowID, window_id: XWindowID, dir: SplitDir) -> BspTree {
+        // Splits alternate direction going down the tree, the root splitting side by side
+        match self {
+            BspTree::Leaf(id) if id == target => return BspTree::Split {
+                dir: dir,
+                ratio: 0.5,
+                left: Box::new(BspTree::Leaf(id)),
+                right: Box::new(BspTree::Leaf(window_id)),
+            },
+            BspTree::Leaf(id) => return BspTree::Leaf(id),
+            BspTree::Split { dir: split_dir, ratio, left, right } => return BspTree::Split {
+                dir: split_dir,
+                ratio: ratio,
+                left: Box::new(left.split_leaf(target, window_id, split_dir.flip())),
+                right: Box::new(right.split_leaf(target, window_id, split_dir.flip())),
+            },
+        }
+    }
+
+    pub fn remove(self, window_id: XWindowID) -> Option<BspTree> {
+        // Removing a leaf gives its whole split to the sibling
+        match self {
+            BspTree::Leaf(id) if id == window_id => return None,
+            BspTree::Leaf(id) => return Some(BspTree::Leaf(id)),
+            BspTree::Split { dir, ratio, left, right } => {
+                match (left.remove(window_id), right.remove(window_id)) {
+                    (Some(left), Some(right)) => return Some(BspTree::Split { dir: dir, ratio: ratio, left: Box::new(left), right: Box::new(right) }),
+                    (Some(only), None) | (None, Some(only)) => return Some(only),
+                    (None, None) => return None,
+                }
+            },
+        }
+    }
+
+    pub fn contains(&self, window_id: XWindowID) -> bool {
+        match self {
+            BspTree::Leaf(id) => return *id == window_id,
+            BspTree::Split { left, right, .. } => return left.contains(window_id) || right.contains(window_id),
+        }
+    }
+
+    pub fn leaves(&self) -> Vec<XWindowID> {
+        return self.leaves_with_area(1.0).into_iter().map(|(id, _)| id).collect();
+    }
+
+    fn leaves_with_area(&self, area: f32) -> Vec<(XWindowID, f32)> {
+        // Window ids left to right, with the fraction of the whole each covers
+        match self {
+            BspTree::Leaf(id) => return vec![(*id, area)],
+            BspTree::Split { ratio, left, right, .. } => {
+                let mut leaves = left.leaves_with_area(area * ratio);
+                leaves.extend(right.leaves_with_area(area * (1.0 - ratio)));
+                return leaves;
+            },
+        }
+    }
+
+    pub fn rotate(&mut self, window_id: XWindowID) -> bool {
+        // Flip the split directly holding the window
+        if let Some((dir, _, _)) = self.parent_of(window_id) {
+            *dir = dir.flip();
+            return true;
+        }
+        return false;
+    }
+
+    pub fn adjust_ratio(&mut self, window_id: XWindowID, delta: f32) -> bool {
+        // Grow (or shrink) the window's side of the split directly holding it
+        if let Some((_, ratio, is_left)) = self.parent_of(window_id) {
+            let delta = if is_left { delta } else { -delta };
+            *ratio = (*ratio + delta).max(BSP_RATIO_MIN).min(BSP_RATIO_MAX);
+            return true;
+        }
+        return false;
+    }
+
+    pub fn balance(&mut self) {
+        // Every split back to even
+        if let BspTree::Split { ratio, left, right, .. } = self {
+            *ratio = 0.5;
+            left.balance();
+            right.balance();
+        }
+    }
+
+    fn parent_of(&mut self, window_id: XWindowID) -> Option<(&mut SplitDir, &mut f32, bool)> {
+        // Split with the window as a direct child, and whether it's the left one
+        match self {
+            BspTree::Leaf(_) => return None,
+            BspTree::Split { dir, ratio, left, right } => {
+                let is_left = match (left.as_ref(), right.as_ref()) {
+                    (BspTree::Leaf(id), _) if *id == window_id => Some(true),
+                    (_, BspTree::Leaf(id)) if *id == window_id => Some(false),
+                    _ => None,
+                };
+                match is_left {
+                    Some(is_left) => return Some((dir, ratio, is_left)),
+                    None if left.contains(window_id) => return left.parent_of(window_id),
+                    None => return right.parent_of(window_id),
+                }
+            },
+        }
+    }
+}
+
+pub fn geometries(tree: &BspTree, area: Geometry, gap: i32) -> HashMap<XWindowID, Geometry> {
+    let mut geometries = HashMap::new();
+    geometries_into(tree, area, gap, &mut geometries);
+    return geometries;
+}
+
+fn geometries_into(tree: &BspTree, area: Geometry, gap: i32, geometries: &mut HashMap<XWindowID, Geometry>) {
+    match tree {
+        BspTree::Leaf(id) => {
+            geometries.insert(*id, area);
+        },
+        BspTree::Split { dir, ratio, left, right } => {
+            // Gap between the two sides, the right side taking any leftover pixels
+            let (left_area, right_area) = match dir {
+                SplitDir::Horizontal => {
+                    let width = ((area.width - gap) as f32 * ratio) as i32;
+                    (Geometry { width: width, ..area }, Geometry { x: area.x + width + gap, width: area.width - width - gap, ..area })
+                },
+                SplitDir::Vertical => {
+                    let height = ((area.height - gap) as f32 * ratio) as i32;
+                    (Geometry { height: height, ..area }, Geometry { y: area.y + height + gap, height: area.height - height - gap, ..area })
+                },
+            };
+            geometries_into(left, left_area, gap, geometries);
+            geometries_into(right, right_area, gap, geometries);
+        },
+    }
+}
+
+pub fn activate(ws: &mut Workspace, conn: &XConn, screen: &Screen) {
+    // If empty, this is pointless
+    if ws.windows.is_empty() {
+        return;
+    }
+
+    // Lay out windows before mapping
+    arrange(ws, conn, screen);
+
+    // Iterate windows
+    for window in ws.windows.iter_rev() {
+        // Map the window to the display
+        conn.map_window(window.xwindow.id);
+    }
+
+    // Tell X to focus our focused window
+    if let Some(window) = ws.windows.focused() {
+        conn.set_input_focus(window.xwindow.id);
+    }
+}
+
+pub fn deactivate(ws: &mut Workspace, conn: &XConn) {
+    // Unmapping is no different to floating
+    floating::deactivate(ws, conn);
+}
+
+pub fn arrange(ws: &mut Workspace, conn: &XConn, screen: &Screen) {
+    // Bring the tree in line with the tiled windows, dropping any gone (closed, floated, fullscreened)
+    // and splitting for any new, in window order
+    let tiled: Vec<XWindowID> = ws.windows.iter().filter(|window| window.is_tiled()).map(|window| window.xwindow.id).collect();
+    let mut tree = ws.bsp.take();
+    for window_id in tree.as_ref().map_or(Vec::new(), |tree| tree.leaves()) {
+        if !tiled.contains(&window_id) {
+            tree = tree.and_then(|tree| tree.remove(window_id));
+        }
+    }
+    for window_id in tiled.iter() {
+        if !tree.as_ref().map_or(false, |tree| tree.contains(*window_id)) {
+            tree = Some(match tree {
+                Some(tree) => tree.insert(*window_id),
+                None => BspTree::Leaf(*window_id),
+            });
+        }
+    }
+    ws.bsp = tree;
+
+    // If empty, nothing to arrange
+    let tree = match ws.bsp.as_ref() {
+        Some(tree) => tree,
+        None => return,
+    };
+
+    // Get screen geometry, less any space reserved by docks and the outer gap. A lone window
+    // covers the screen entirely, borderless, if configured
+    let single = AUTO_FULLSCREEN_SINGLE && tiled.len() == 1;
+    let area = if single {
+        Geometry { x: screen.xwindow.x, y: screen.xwindow.y, width: screen.xwindow.width, height: screen.xwindow.height }
+    } else {
+        let area = screen.usable_area();
+        let outer = THEME.gap_outer as i32;
+        Geometry { x: area.x + outer, y: area.y + outer, width: area.width - 2 * outer, height: area.height - 2 * outer }
+    };
+    let geometries = geometries(tree, area, THEME.gap_inner as i32);
+
+    for window in ws.windows.iter_mut().filter(|window| window.is_tiled()) {
+        // Take away the border while alone, else put back any taken away
+        let border_width = if single { 0 } else { window.border_width(&THEME) };
+        if AUTO_FULLSCREEN_SINGLE {
+            conn.set_border_width(&THEME, window.xwindow.id, border_width);
+        }
+
+        // Window geometry excludes borders, so account for them on both sides
+        let border = 2 * border_width as i32;
+        let geometry = geometries.get(&window.xwindow.id).unwrap();
+        window.set_geometry(conn, geometry.x, geometry.y, geometry.width - border, geometry.height - border);
+    }
+}
+
+pub fn window_add(ws: &mut Workspace, conn: &XConn, screen: &Screen, window: Window, focus: bool) {
+    // Get window id just the once
+    let window_id = window.xwindow.id;
+
+    // Start tracking events for this window
+    conn.change_window_attributes(window_id, &helper::values_attributes_child_events());
+
+    // Internally add, leaving focus where it is if not focusing
+    if focus {
+        ws.windows.add(window);
+    } else {
+        ws.windows.add_unfocused(window);
+    }
+
+    // Split space for the new window
+    arrange(ws, conn, screen);
+
+    // Tell X to map and focus the window, the workspace stacks floating windows ontop of tiled
+    conn.map_window(window_id);
+    if focus {
+        conn.set_input_focus(window_id);
+    }
+}
+
+pub fn window_del(ws: &mut Workspace, conn: &XConn, screen: &Screen, idx: usize, window_id: XWindowID) -> Window {
+    // Get window and own_
+    let window = ws.windows.get(idx).unwrap().to_owned();
+
+    // Check if this was focused before removing
+    let was_focused = ws.windows.is_focused(window_id);
+
+    // Internally remove window at position
+    ws.windows.remove(idx);
+
+    // Stop tracking events for this window
+    conn.change_window_attributes(window_id, &helper::values_attributes_no_events());
+
+    // Tell X to unmap the window
+    conn.unmap_window(window_id);
+
+    // Put back any border taken away while alone, it may be going to another workspace
+    if AUTO_FULLSCREEN_SINGLE && !window.fullscreen {
+        conn.set_border_width(&THEME, window_id, window.border_width(&THEME));
+    }
+
+    // Give its space to its sibling
+    arrange(ws, conn, screen);
+
+    // If we just deleted the previously focused, focus whatever is focused now
+    if was_focused {
+        if let Some(window) = ws.windows.focused() { conn.set_input_focus(window.xwindow.id); }
+    }
+
+    // Return the Window
+    return window;
+}
+
+pub fn window_focus(ws: &mut Workspace, conn: &XConn, screen: &Screen, idx: usize) {
+    // Windows don't change position on focus, no different to tiling
+    tiling::window_focus(ws, conn, screen, idx);
+}
+
+pub fn window_focus_cycle(ws: &mut Workspace, conn: &XConn, screen: &Screen) {
+    tiling::window_focus_cycle(ws, conn, screen);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(ids: &[XWindowID]) -> BspTree {
+        let mut tree = BspTree::Leaf(ids[0]);
+        for id in ids[1..].iter() {
+            tree = tree.insert(*id);
+        }
+        return tree;
+    }
+
+    fn root_ratio(tree: &BspTree) -> f32 {
+        match tree {
+            BspTree::Split { ratio, .. } => return *ratio,
+            BspTree::Leaf(_) => panic!("expected a split"),
+        }
+    }
+
+    #[test]
+    fn insert_splits_larger_half() {
+        // Even halves, the later one splits
+        assert_eq!(tree(&[1, 2, 3]).leaves(), vec![1, 2, 3]);
+        let mut tree = tree(&[1, 2]);
+
+        // Left grown larger, so it splits instead
+        tree.adjust_ratio(1, 0.2);
+        let tree = tree.insert(3);
+        assert_eq!(tree.leaves(), vec![1, 3, 2]);
+    }
+
+    #[test]
+    fn insert_alternates_direction() {
+        match tree(&[1, 2, 3]) {
+            BspTree::Split { dir: SplitDir::Horizontal, right, .. } => match *right {
+                BspTree::Split { dir, .. } => assert_eq!(dir, SplitDir::Vertical),
+                BspTree::Leaf(_) => panic!("expected right to split"),
+            },
+            _ => panic!("expected a side by side split at the root"),
+        }
+    }
+
+    #[test]
+    fn remove_gives_space_to_sibling() {
+        let area = Geometry { x: 0, y: 0, width: 1000, height: 500 };
+        let tree = tree(&[1, 2, 3]).remove(2).unwrap();
+        assert_eq!(tree.leaves(), vec![1, 3]);
+        assert_eq!(geometries(&tree, area, 10).get(&3), Some(&Geometry { x: 505, y: 0, width: 495, height: 500 }));
+
+        // Last window gone leaves nothing
+        assert!(BspTree::Leaf(1).remove(1).is_none());
+    }
+
+    #[test]
+    fn ratio_clamped() {
+        let mut tree = tree(&[1, 2]);
+        tree.adjust_ratio(1, 1.0);
+        assert_eq!(root_ratio(&tree), BSP_RATIO_MAX);
+
+        // Growing the right side shrinks the ratio
+        tree.adjust_ratio(2, 1.0);
+        assert_eq!(root_ratio(&tree), BSP_RATIO_MIN);
+
+        tree.balance();
+        assert_eq!(root_ratio(&tree), 0.5);
+        assert!(!tree.adjust_ratio(3, 0.1));
+    }
+
+    #[test]
+    fn geometries_with_gap() {
+        let area = Geometry { x: 0, y: 0, width: 1000, height: 500 };
+        let geometries = geometries(&tree(&[1, 2, 3]), area, 10);
+        assert_eq!(geometries.get(&1), Some(&Geometry { x: 0, y: 0, width: 495, height: 500 }));
+        assert_eq!(geometries.get(&2), Some(&Geometry { x: 505, y: 0, width: 495, height: 245 }));
+        assert_eq!(geometries.get(&3), Some(&Geometry { x: 505, y: 255, width: 495, height: 245 }));
+
+        // Lone window gets the whole area
+        assert_eq!(super::geometries(&BspTree::Leaf(1), area, 10).get(&1), Some(&area));
+    }
+}
@@ -1,4 +1,6 @@
+use crate::config::{CASCADE_OFFSET, PLACEMENT, THEME};
 use crate::helper;
+use crate::layout::Placement;
 use crate::screen::Screen;
 use crate::windows::Window;
 use crate::workspace::Workspace;
@@ -17,32 +19,43 @@ pub fn activate(ws: &mut Workspace, conn: &XConn, screen: &Screen) {
     }
 
     // Tell X to focus our focused window
-    conn.set_input_focus(ws.windows.focused().unwrap().xwindow.id);
+    if let Some(window) = ws.windows.focused() {
+        conn.set_input_focus(window.xwindow.id);
+    }
 }
 
 pub fn deactivate(ws: &mut Workspace, conn: &XConn) {
     // Iterate windows
     for window in ws.windows.iter() {
-        // Disable events before unmapping the window
-        conn.change_window_attributes(window.xwindow.id, &helper::values_attributes_no_events());
+        // Unmap the window, keeping it tracked
+        conn.hide_window(window.xwindow.id);
+    }
+}
 
-        // Unmap the window
-        conn.unmap_window(window.xwindow.id);
+pub fn arrange(_ws: &mut Workspace, _conn: &XConn, _screen: &Screen) {
+    // Floating windows keep whatever geometry they were given, nothing to do
+}
 
-        // Enable events again
-        conn.change_window_attributes(window.xwindow.id, &helper::values_attributes_child_events());
+pub fn window_add(ws: &mut Workspace, conn: &XConn, screen: &Screen, mut window: Window, focus: bool) {
+    // Place the window before it's shown, transients are already centered over their parent
+    if window.transient_for.is_none() {
+        place(ws, screen, &mut window);
     }
-}
 
-pub fn window_add(ws: &mut Workspace, conn: &XConn, screen: &Screen, window: Window) {
-    // Tell X to map and focus the window
+    // Position the window, stacking is left to the workspace
+    conn.configure_window(window.xwindow.id, &helper::values_configure_move(window.xwindow.x as u32, window.xwindow.y as u32));
+
+    // Tell X to map the window
     conn.map_window(window.xwindow.id);
 
     // Start tracking events for this window
     conn.change_window_attributes(window.xwindow.id, &helper::values_attributes_child_events());
 
-    // Set window ontop
-    conn.configure_window(window.xwindow.id, &helper::values_configure_stack_above());
+    // Not focusing, internally add leaving focus where it is
+    if !focus {
+        ws.windows.add_unfocused(window);
+        return;
+    }
 
     // Set focused
     conn.set_input_focus(window.xwindow.id);
@@ -55,6 +68,9 @@ pub fn window_del(ws: &mut Workspace, conn: &XConn, screen: &Screen, idx: usize,
     // Get window and own_
     let window = ws.windows.get(idx).unwrap().to_owned();
 
+    // Check if this was focused before removing
+    let was_focused = ws.windows.is_focused(window_id);
+
     // Internally remove window at position
     ws.windows.remove(idx);
 
@@ -64,24 +80,23 @@ pub fn window_del(ws: &mut Workspace, conn: &XConn, screen: &Screen, idx: usize,
     // Tell X to unmap the window
     conn.unmap_window(window_id);
 
-    // If we just deleted the previously focused, try focus the next index 0
-    if idx == 0 {
-        if let Some(window) = ws.windows.get(0) { window_input_focus_set_ontop(conn, window.xwindow.id); }
+    // If we just deleted the previously focused, focus whichever had focus before it
+    if was_focused {
+        if let Some(idx) = ws.windows.focused_idx() { window_focus(ws, conn, screen, idx); }
     }
 
     // Return the Window
     return window;
 }
 
-pub fn window_focus(ws: &mut Workspace, conn: &XConn, screen: &Screen, window_id: XWindowID) {
-    // Focus window (if there!)
-    if let Some(idx) = ws.windows.index_of(window_id) {
-        // Internally, move to front
-        ws.windows.move_front(idx);
+pub fn window_focus(ws: &mut Workspace, conn: &XConn, screen: &Screen, idx: usize) {
+    // Internally, move to front and focus
+    ws.windows.move_front(idx);
+    ws.windows.set_focused(0);
 
-        // Focus input + set ontop
-        window_input_focus_set_ontop(conn, window_id);
-    }
+    // Focus input
+    let window_id = ws.windows.get(0).unwrap().xwindow.id;
+    window_input_focus(conn, window_id);
 }
 
 pub fn window_focus_cycle(ws: &mut Workspace, conn: &XConn, screen: &Screen) {
@@ -93,26 +108,94 @@ pub fn window_focus_cycle(ws: &mut Workspace, conn: &XConn, screen: &Screen) {
         return;
     }
 
-    // Internally, move last window to front
+    // Internally, move last window to front and focus
     ws.windows.move_front(len-1);
+    ws.windows.set_focused(0);
 
     // Get window in question
-    let window = ws.windows.get(len-1).unwrap();
+    let window = ws.windows.focused().unwrap();
 
-    // Focus input + set ontop
-    window_input_focus_set_ontop(conn, window.xwindow.id);
+    // Focus input
+    window_input_focus(conn, window.xwindow.id);
 }
 
-fn window_input_focus_set_ontop(conn: &XConn, window_id: XWindowID) {
-    // Disable event tracking before making changes
-    conn.change_window_attributes(window_id, &helper::values_attributes_no_events());
+fn place(ws: &Workspace, screen: &Screen, window: &mut Window) {
+    // Get placement according to strategy
+    let (x, y) = match PLACEMENT {
+        Placement::Cascade => place_cascade(ws, screen, window),
+        Placement::Smart => place_smart(ws, screen, window).unwrap_or_else(|| place_cascade(ws, screen, window)),
+    };
 
-    // Set window ontop
-    conn.configure_window(window_id, &helper::values_configure_stack_above());
+    // Move the window there, X gets told when it's mapped
+    window.offset(screen, x - window.xwindow.x, y - window.xwindow.y);
+}
 
-    // Tell X to focus the window
-    conn.set_input_focus(window_id);
+fn place_cascade(ws: &Workspace, screen: &Screen, window: &Window) -> (i32, i32) {
+    // Outer size, including borders
+    let width = window.xwindow.width + 2 * window.border_width(&THEME) as i32;
+    let height = window.xwindow.height + 2 * window.border_width(&THEME) as i32;
 
-    // Enable event tracking again
-    conn.change_window_attributes(window_id, &helper::values_attributes_child_events());
+    // Place within the screen, avoiding space reserved by docks
+    let area = screen.usable_area();
+
+    // Offset from the last placed window (always at the front), else screen origin
+    let (x, y) = match ws.windows.get(0) {
+        Some(last) => (last.xwindow.x + CASCADE_OFFSET, last.xwindow.y + CASCADE_OFFSET),
+        None => (area.x, area.y),
+    };
+
+    // Start over from the screen origin if that would run off-screen
+    if x + width > area.x + area.width || y + height > area.y + area.height {
+        return (area.x, area.y);
+    }
+
+    return (x, y);
+}
+
+fn place_smart(ws: &Workspace, screen: &Screen, window: &Window) -> Option<(i32, i32)> {
+    // Outer size, including borders
+    let width = window.xwindow.width + 2 * window.border_width(&THEME) as i32;
+    let height = window.xwindow.height + 2 * window.border_width(&THEME) as i32;
+
+    // Place within the screen, avoiding space reserved by docks
+    let area = screen.usable_area();
+
+    // Occupied outer rectangles of (x, y, width, height)
+    let occupied: Vec<(i32, i32, i32, i32)> = ws.windows.iter()
+        .map(|w| (w.xwindow.x, w.xwindow.y, w.xwindow.width + 2 * w.border_width(&THEME) as i32, w.xwindow.height + 2 * w.border_width(&THEME) as i32))
+        .collect();
+
+    // Candidate positions are the screen origin, and right / bottom edges of existing windows
+    let mut xs: Vec<i32> = vec![area.x];
+    let mut ys: Vec<i32> = vec![area.y];
+    for (x, y, w, h) in occupied.iter() {
+        xs.push(x + w);
+        ys.push(y + h);
+    }
+    xs.sort();
+    ys.sort();
+
+    // Scan top-left to bottom-right, taking the first gap the window fits in
+    for y in ys.iter() {
+        for x in xs.iter() {
+            // Must be within the screen
+            if *x < area.x || *y < area.y ||
+               x + width > area.x + area.width ||
+               y + height > area.y + area.height {
+                continue;
+            }
+
+            // Must not overlap any existing window
+            if occupied.iter().all(|(ox, oy, ow, oh)| x + width <= *ox || *x >= ox + ow || y + height <= *oy || *y >= oy + oh) {
+                return Some((*x, *y));
+            }
+        }
+    }
+
+    return None;
+}
+
+fn window_input_focus(conn: &XConn, window_id: XWindowID) {
+    // Tell X to focus the window, the workspace raises it
+    conn.set_input_focus(window_id);
 }
\ No newline at end of file
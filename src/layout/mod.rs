@@ -1,6 +1,57 @@
+pub mod bsp;
 pub mod floating;
+pub mod tiling;
 
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum LayoutType {
     Floating,
-//    Tiling,
-}
\ No newline at end of file
+    Tiling,
+    Bsp,
+}
+
+impl LayoutType {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            LayoutType::Floating => return "><>",
+            LayoutType::Tiling => return "[]=",
+            LayoutType::Bsp => return "[@]",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LayoutType::Floating => return "floating",
+            LayoutType::Tiling => return "tiling",
+            LayoutType::Bsp => return "bsp",
+        }
+    }
+
+    pub fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => return Some(LayoutType::Floating),
+            1 => return Some(LayoutType::Tiling),
+            2 => return Some(LayoutType::Bsp),
+            _ => return None,
+        }
+    }
+}
+
+// Whether new windows get focused. Chosen in config, so not every variant is constructed
+#[allow(dead_code)]
+pub enum FocusNew {
+    Always,
+    Never,
+
+    // Only if _NET_WM_USER_TIME shows user interaction since our last input event
+    Smart,
+}
+
+// Placement strategy for new floating windows. Chosen in config, so not every variant is constructed
+#[allow(dead_code)]
+pub enum Placement {
+    // Offset from the last placed window
+    Cascade,
+
+    // First free gap large enough, else cascade
+    Smart,
+}
@@ -0,0 +1,214 @@
+use crate::config::{AUTO_FULLSCREEN_SINGLE, THEME};
+use crate::helper;
+use crate::layout::floating;
+use crate::screen::Screen;
+use crate::windows::{Geometry, Window};
+use crate::workspace::Workspace;
+use crate::x::{XConn, XWindowID};
+
+pub fn activate(ws: &mut Workspace, conn: &XConn, screen: &Screen) {
+    // If empty, this is pointless
+    if ws.windows.is_empty() {
+        return;
+    }
+
+    // Lay out windows before mapping
+    arrange(ws, conn, screen);
+
+    // Iterate windows
+    for window in ws.windows.iter_rev() {
+        // Map the window to the display
+        conn.map_window(window.xwindow.id);
+    }
+
+    // Tell X to focus our focused window
+    if let Some(window) = ws.windows.focused() {
+        conn.set_input_focus(window.xwindow.id);
+    }
+}
+
+pub fn deactivate(ws: &mut Workspace, conn: &XConn) {
+    // Unmapping is no different to floating
+    floating::deactivate(ws, conn);
+}
+
+pub fn arrange(ws: &mut Workspace, conn: &XConn, screen: &Screen) {
+    // Get length of tiled windows just the once, fullscreen / floating windows aren't tiled
+    let len = ws.windows.iter().filter(|window| window.is_tiled()).count();
+
+    // If empty, nothing to arrange
+    if len == 0 {
+        return;
+    }
+
+    // Get screen geometry, less any space reserved by docks and the outer gap
+    let area = screen.usable_area();
+    let (outer, inner) = (THEME.gap_outer as i32, THEME.gap_inner as i32);
+    let (sx, sy, sw, sh) = (area.x + outer, area.y + outer, area.width - 2 * outer, area.height - 2 * outer);
+
+    // Single window takes up the whole screen, or covers it entirely if configured.
+    // Window geometry excludes borders, so account for them on both sides
+    if len == 1 {
+        let window = ws.windows.iter_mut().find(|window| window.is_tiled()).unwrap();
+        if AUTO_FULLSCREEN_SINGLE {
+            conn.set_border_width(&THEME, window.xwindow.id, 0);
+            window.set_geometry(conn, screen.xwindow.x, screen.xwindow.y, screen.xwindow.width, screen.xwindow.height);
+            return;
+        }
+        let border = 2 * window.border_width(&THEME) as i32;
+        window.set_geometry(conn, sx, sy, sw - border, sh - border);
+        return;
+    }
+
+    let area = Geometry { x: sx, y: sy, width: sw, height: sh };
+    let geometries = geometries(len, ws.master_count, ws.master_factor, area, inner);
+    for (window, geometry) in ws.windows.iter_mut().filter(|window| window.is_tiled()).zip(geometries) {
+        // Put back any border taken away while alone
+        if AUTO_FULLSCREEN_SINGLE {
+            conn.set_border_width(&THEME, window.xwindow.id, window.border_width(&THEME));
+        }
+
+        // Window geometry excludes borders, so account for them on both sides
+        let border = 2 * window.border_width(&THEME) as i32;
+        window.set_geometry(conn, geometry.x, geometry.y, geometry.width - border, geometry.height - border);
+    }
+}
+
+pub fn geometries(len: usize, master_count: usize, master_factor: f32, area: Geometry, gap: i32) -> Vec<Geometry> {
+    // Split windows between master and stack, with masters spanning the area if there's no stack
+    let master_len = master_count.min(len).max(1) as i32;
+    let stack_len = len as i32 - master_len;
+    let master_width = if stack_len == 0 { area.width } else { (area.width as f32 * master_factor) as i32 };
+
+    // Master windows split the left, stack windows split the right, gap between each. Last in each takes any leftover pixels
+    let master_height = (area.height - gap * (master_len - 1)) / master_len;
+    let stack_height = if stack_len == 0 { 0 } else { (area.height - gap * (stack_len - 1)) / stack_len };
+
+    return (0..len as i32).map(|pos| {
+        if pos < master_len {
+            let y = area.y + (master_height + gap) * pos;
+            let height = if pos == master_len - 1 { area.y + area.height - y } else { master_height };
+            return Geometry { x: area.x, y: y, width: master_width, height: height };
+        }
+        let pos = pos - master_len;
+        let y = area.y + (stack_height + gap) * pos;
+        let height = if pos == stack_len - 1 { area.y + area.height - y } else { stack_height };
+        return Geometry { x: area.x + master_width + gap, y: y, width: area.width - master_width - gap, height: height };
+    }).collect();
+}
+
+pub fn window_add(ws: &mut Workspace, conn: &XConn, screen: &Screen, window: Window, focus: bool) {
+    // Get window id just the once
+    let window_id = window.xwindow.id;
+
+    // Start tracking events for this window
+    conn.change_window_attributes(window_id, &helper::values_attributes_child_events());
+
+    // Internally add, leaving focus where it is if not focusing
+    if focus {
+        ws.windows.add(window);
+    } else {
+        ws.windows.add_unfocused(window);
+    }
+
+    // Re-tile with the new window
+    arrange(ws, conn, screen);
+
+    // Tell X to map and focus the window, the workspace stacks floating windows ontop of tiled
+    conn.map_window(window_id);
+    if focus {
+        conn.set_input_focus(window_id);
+    }
+}
+
+pub fn window_del(ws: &mut Workspace, conn: &XConn, screen: &Screen, idx: usize, window_id: XWindowID) -> Window {
+    // Get window and own_
+    let window = ws.windows.get(idx).unwrap().to_owned();
+
+    // Check if this was focused before removing
+    let was_focused = ws.windows.is_focused(window_id);
+
+    // Internally remove window at position
+    ws.windows.remove(idx);
+
+    // Stop tracking events for this window
+    conn.change_window_attributes(window_id, &helper::values_attributes_no_events());
+
+    // Tell X to unmap the window
+    conn.unmap_window(window_id);
+
+    // Put back any border taken away while alone, it may be going to another workspace
+    if AUTO_FULLSCREEN_SINGLE && !window.fullscreen {
+        conn.set_border_width(&THEME, window_id, window.border_width(&THEME));
+    }
+
+    // Re-tile the remaining windows
+    arrange(ws, conn, screen);
+
+    // If we just deleted the previously focused, focus whatever is focused now
+    if was_focused {
+        if let Some(window) = ws.windows.focused() { conn.set_input_focus(window.xwindow.id); }
+    }
+
+    // Return the Window
+    return window;
+}
+
+pub fn window_focus(ws: &mut Workspace, conn: &XConn, screen: &Screen, idx: usize) {
+    // Tiled windows don't change position on focus
+    ws.windows.set_focused(idx);
+    conn.set_input_focus(ws.windows.get(idx).unwrap().xwindow.id);
+}
+
+pub fn window_focus_cycle(ws: &mut Workspace, conn: &XConn, screen: &Screen) {
+    // Get length just the once
+    let len = ws.windows.len();
+
+    // If length < 2 nothing to do
+    if len < 2 {
+        return;
+    }
+
+    // Internally, focus next window along (or the first, if focus was lost)
+    ws.windows.set_focused(ws.windows.focused_idx().map_or(0, |idx| (idx + 1) % len));
+
+    // Tell X to focus the window
+    if let Some(window) = ws.windows.focused() {
+        conn.set_input_focus(window.xwindow.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn balanced_factor_gives_equal_areas() {
+        // Balancing is a single master taking 1/n of the width, whatever n
+        let area = Geometry { x: 0, y: 0, width: 1200, height: 660 };
+        for len in [1, 2, 3, 12].iter() {
+            let geometries = geometries(*len, 1, 1.0 / *len as f32, area, 0);
+            assert_eq!(geometries.len(), *len);
+            for geometry in geometries.iter() {
+                assert_eq!(geometry.width * geometry.height, 1200 * 660 / *len as i32, "{} windows: {:?}", len, geometries);
+            }
+        }
+    }
+
+    #[test]
+    fn master_count_splits_left() {
+        let area = Geometry { x: 0, y: 0, width: 1000, height: 600 };
+        assert_eq!(geometries(4, 2, 0.6, area, 10), vec![
+            Geometry { x: 0, y: 0, width: 600, height: 295 },
+            Geometry { x: 0, y: 305, width: 600, height: 295 },
+            Geometry { x: 610, y: 0, width: 390, height: 295 },
+            Geometry { x: 610, y: 305, width: 390, height: 295 },
+        ]);
+
+        // More masters than windows, they span the whole area
+        assert_eq!(geometries(2, 3, 0.6, area, 10), vec![
+            Geometry { x: 0, y: 0, width: 1000, height: 295 },
+            Geometry { x: 0, y: 305, width: 1000, height: 295 },
+        ]);
+    }
+}
@@ -3,8 +3,11 @@ mod log;
 
 mod config;
 mod desktop;
+mod filter;
 mod helper;
+mod ipc;
 mod layout;
+mod scratchpad;
 mod screen;
 mod windows;
 mod wm;
@@ -14,6 +17,7 @@ mod x;
 use wm::WM;
 
 use std::env;
+use std::os::unix::process::CommandExt;
 use std::process;
 use xcb_util::ewmh;
 
@@ -29,7 +33,7 @@ fn print_version() {
 
 fn print_usage() {
     println!(
-        "Usage: {} [-h|--help] [-v|--version] [-y|--why]",
+        "Usage: {} [-h|--help] [-v|--version] [-y|--why] [-m|--modkey <mod1-5|alt|super>] [-w|--workspaces <count>]",
         env!("CARGO_PKG_NAME"),
     )
 }
@@ -38,9 +42,14 @@ fn main() {
     // Get arguments
     let args: Vec<String> = env::args().collect();
 
-    // If arguments provided, either show version or help
-    if args.len() >= 2 {
-        match args.get(1).unwrap().as_str() {
+    // Modkey and workspace count, unless overridden
+    let mut modkey = config::MODKEY;
+    let mut workspaces = config::WORKSPACES.max(config::WORKSPACES_MIN).min(config::WORKSPACES_MAX);
+
+    // If arguments provided, either show version or help, or set options
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
             "-v"|"--version" => {
                 print_version();
                 process::exit(0);
@@ -56,6 +65,26 @@ fn main() {
                 process::exit(69);
             },
 
+            "-m"|"--modkey" => {
+                match args.next().and_then(|name| helper::modkey_from_name(name)) {
+                    Some(mask) => modkey = mask,
+                    None => {
+                        print_usage();
+                        process::exit(1);
+                    },
+                }
+            },
+
+            "-w"|"--workspaces" => {
+                match args.next().and_then(|count| helper::workspaces_from_arg(count)) {
+                    Some(count) => workspaces = count,
+                    None => {
+                        print_usage();
+                        process::exit(1);
+                    },
+                }
+            },
+
             _ => {
                 print_usage();
                 process::exit(1);
@@ -63,10 +92,6 @@ fn main() {
         }
     }
 
-    // Register OS signals
-    unsafe { signal_hook::register(signal_hook::SIGINT|signal_hook::SIGTERM, || { panic!("OS Signal received!") }).expect("Failed to register OS signal receiver"); }
-    debug!("Registered OS signal hook");
-
     // Try connect to xserver
     let (conn, screen_idx) = xcb::Connection::connect(None).expect("Failed to connect to X server");
     debug!("Connected to X server");
@@ -75,8 +100,16 @@ fn main() {
     let conn = ewmh::Connection::connect(conn).map_err(|(err, _)| { err }).expect("Failed to get EWMH connection");
 
     // Create new window manager object
-    let mut wm = WM::register(&conn, screen_idx);
+    let mut wm = WM::register(&conn, screen_idx, modkey, workspaces);
 
     // Run window manager!
     wm.run();
+
+    // If restarting, replace ourselves with a fresh copy run with the same arguments
+    if wm.restarting() {
+        let exe = env::current_exe().expect("Getting current executable");
+        let err = helper::restart_command(exe, env::args().skip(1)).exec();
+        error!("Restarting: {}", err);
+        process::exit(1);
+    }
 }
@@ -0,0 +1,87 @@
+use crate::helper;
+use crate::screen::Screen;
+use crate::windows::Window;
+use crate::x::{XConn, XWindowID};
+
+#[derive(Default)]
+pub struct Scratchpad {
+    // Captured scratchpad window, lives outside of any workspace
+    pub window: Option<Window>,
+
+    // Currently shown
+    pub visible: bool,
+}
+
+impl Scratchpad {
+    pub fn capture(&mut self, conn: &XConn, screen: &Screen, mut window: Window) {
+        debug!("Capturing scratchpad window: {}", window.xwindow.id);
+
+        // Center on screen, geometry is kept as-is between toggles from now on
+        let x = screen.xwindow.x + (screen.xwindow.width - window.xwindow.width) / 2;
+        let y = screen.xwindow.y + (screen.xwindow.height - window.xwindow.height) / 2;
+        window.do_move(conn, screen, x - window.xwindow.x, y - window.xwindow.y);
+
+        // Set window, then show
+        self.window = Some(window);
+        self.show(conn);
+    }
+
+    pub fn release(&mut self) -> Option<Window> {
+        debug!("Releasing scratchpad window");
+        self.visible = false;
+        return self.window.take();
+    }
+
+    pub fn toggle(&mut self, conn: &XConn) {
+        if self.visible {
+            self.hide(conn);
+        } else {
+            self.show(conn);
+        }
+    }
+
+    pub fn show(&mut self, conn: &XConn) {
+        if let Some(window) = &self.window {
+            debug!("Showing scratchpad window: {}", window.xwindow.id);
+
+            // Disable event tracking before making changes
+            conn.change_window_attributes(window.xwindow.id, &helper::values_attributes_no_events());
+
+            // Set ontop and map
+            conn.configure_window(window.xwindow.id, &helper::values_configure_stack_above());
+            conn.map_window(window.xwindow.id);
+
+            // Enable event tracking again
+            conn.change_window_attributes(window.xwindow.id, &helper::values_attributes_child_events());
+
+            // Focus, after enabling events so we receive FocusIn
+            conn.set_input_focus(window.xwindow.id);
+
+            self.visible = true;
+        }
+    }
+
+    pub fn hide(&mut self, conn: &XConn) {
+        if let Some(window) = &self.window {
+            debug!("Hiding scratchpad window: {}", window.xwindow.id);
+
+            // Unmap window, keeping it tracked
+            conn.hide_window(window.xwindow.id);
+
+            self.visible = false;
+        }
+    }
+
+    pub fn raise(&self, conn: &XConn) {
+        // Keep on top of any newly mapped workspace windows
+        if let Some(window) = &self.window {
+            if self.visible {
+                conn.configure_window(window.xwindow.id, &helper::values_configure_stack_above());
+            }
+        }
+    }
+
+    pub fn contains(&self, window_id: XWindowID) -> bool {
+        return self.window.as_ref().map_or(false, |window| window.xwindow.id == window_id);
+    }
+}
@@ -1,8 +1,49 @@
-use crate::x::{XWindow, XWindowID};
+use crate::windows::Geometry;
+use crate::x::{XConn, XWindow, XWindowID};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+fn adjacent_monitor(monitors: &[(i32, i32, i32, i32)], monitor: usize, direction: Direction) -> Option<usize> {
+    let (cx, cy, _, _) = *monitors.get(monitor)?;
+
+    // Of the monitors whose origin lies in the direction, take the nearest along it, then across it
+    return monitors.iter().enumerate()
+        .filter_map(|(idx, (x, y, _, _))| {
+            let (along, across) = match direction {
+                Direction::Left => (cx - x, y - cy),
+                Direction::Right => (x - cx, y - cy),
+                Direction::Up => (cy - y, x - cx),
+                Direction::Down => (y - cy, x - cx),
+            };
+            if along > 0 { Some((along, across.abs(), idx)) } else { None }
+        })
+        .min()
+        .map(|(_, _, idx)| idx);
+}
 
 pub struct Screen {
     pub xwindow: XWindow,
     pub idx: i32,
+
+    // Index of the monitor (RandR CRTC or Xinerama head) being managed, if there are any
+    pub monitor: usize,
+
+    // Space reserved at the (left, right, top, bottom) root window edges by docks / panels,
+    // the largest of each edge from the struts of each dock that reach this monitor
+    pub struts: [i32; 4],
+
+    // Partial struts of each dock, as (left, right, top, bottom) widths then (start, end) extents along each edge
+    docks: Vec<(XWindowID, [i32; 12])>,
+
+    // Root window size, which struts are relative to
+    root_width: i32,
+    root_height: i32,
 }
 
 impl Screen {
@@ -10,6 +51,190 @@ impl Screen {
         Self {
             xwindow: XWindow::from(root_id),
             idx: screen_idx,
+            monitor: 0,
+            struts: [0; 4],
+            docks: Vec::new(),
+            root_width: 0,
+            root_height: 0,
+        }
+    }
+
+    pub fn for_monitor(&self, conn: &XConn, monitor: usize) -> Self {
+        // Same root window and docks, narrowed down to another monitor
+        let mut screen = Self {
+            xwindow: XWindow::from(self.xwindow.id),
+            idx: self.idx,
+            monitor: monitor,
+            struts: [0; 4],
+            docks: self.docks.clone(),
+            root_width: 0,
+            root_height: 0,
+        };
+        screen.update_geometry(conn);
+        return screen;
+    }
+
+    pub fn update_geometry(&mut self, conn: &XConn) {
+        // Start with the root window geometry, covering all monitors
+        self.xwindow.update_geometry(conn);
+        self.root_width = self.xwindow.width;
+        self.root_height = self.xwindow.height;
+
+        // Narrow down to the managed monitor, falling back to the first if it's gone
+        let monitors = conn.get_monitors(self.xwindow.id);
+        if self.monitor >= monitors.len() {
+            self.monitor = 0;
+        }
+        if let Some((x, y, width, height)) = monitors.get(self.monitor) {
+            debug!("Using monitor {} geometry: {} {} {} {}", self.monitor, x, y, width, height);
+            self.xwindow.x = *x;
+            self.xwindow.y = *y;
+            self.xwindow.width = *width;
+            self.xwindow.height = *height;
+        }
+
+        // Docks reaching this monitor may have changed
+        self.update_struts();
+    }
+
+    pub fn adjacent_monitor(&self, conn: &XConn, direction: Direction) -> Option<usize> {
+        return adjacent_monitor(&conn.get_monitors(self.xwindow.id), self.monitor, direction);
+    }
+
+    pub fn next_monitor(&self, conn: &XConn) -> Option<usize> {
+        // Following monitor in the order X lists them, wrapping around, if there's more than one
+        let len = conn.get_monitors(self.xwindow.id).len();
+        if len < 2 {
+            return None;
+        }
+        return Some((self.monitor + 1) % len);
+    }
+
+    pub fn strut_from_legacy(&self, strut: [i32; 4]) -> [i32; 12] {
+        // Old style struts span the whole edge, i.e. the full root window height / width
+        let [left, right, top, bottom] = strut;
+        let (height, width) = (self.root_height - 1, self.root_width - 1);
+        return [left, right, top, bottom, 0, height, 0, height, 0, width, 0, width];
+    }
+
+    pub fn set_strut(&mut self, window_id: XWindowID, strut: Option<[i32; 12]>) -> bool {
+        // Replace any previous strut for this dock
+        self.docks.retain(|(dock_id, _)| *dock_id != window_id);
+        if let Some(strut) = strut {
+            self.docks.push((window_id, strut));
+        }
+
+        // Recalculate, reporting whether anything changed
+        return self.update_struts();
+    }
+
+    fn update_struts(&mut self) -> bool {
+        // Left / right extents run along y, top / bottom along x
+        let (y_start, y_end) = (self.xwindow.y, self.xwindow.y + self.xwindow.height - 1);
+        let (x_start, x_end) = (self.xwindow.x, self.xwindow.x + self.xwindow.width - 1);
+        let ranges = [(y_start, y_end), (y_start, y_end), (x_start, x_end), (x_start, x_end)];
+
+        // Take the largest of each edge, from docks whose extent overlaps this monitor
+        let mut struts = [0; 4];
+        for (_, strut) in self.docks.iter() {
+            for edge in 0..4 {
+                let (start, end) = (strut[4 + edge * 2], strut[5 + edge * 2]);
+                let (range_start, range_end) = ranges[edge];
+                if start <= range_end && end >= range_start {
+                    struts[edge] = struts[edge].max(strut[edge]);
+                }
+            }
         }
+        if struts == self.struts {
+            return false;
+        }
+        debug!("Setting struts: {:?}", struts);
+        self.struts = struts;
+        return true;
+    }
+
+    pub fn usable_area(&self) -> Geometry {
+        // Screen geometry minus whatever the struts reserve of it
+        let [left, right, top, bottom] = self.struts;
+        let x = self.xwindow.x.max(left);
+        let y = self.xwindow.y.max(top);
+        let width = (self.xwindow.x + self.xwindow.width).min(self.root_width - right) - x;
+        let height = (self.xwindow.y + self.xwindow.height).min(self.root_height - bottom) - y;
+        return Geometry { x: x, y: y, width: width, height: height };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Second of two 1920x1080 monitors side by side
+    fn screen(struts: [i32; 4]) -> Screen {
+        let mut screen = Screen::new(0, 1);
+        screen.monitor = 1;
+        screen.xwindow.x = 1920;
+        screen.xwindow.width = 1920;
+        screen.xwindow.height = 1080;
+        screen.root_width = 3840;
+        screen.root_height = 1080;
+        screen.struts = struts;
+        return screen;
+    }
+
+    #[test]
+    fn usable_area_without_struts() {
+        assert_eq!(screen([0; 4]).usable_area(), Geometry { x: 1920, y: 0, width: 1920, height: 1080 });
+    }
+
+    #[test]
+    fn usable_area_less_struts() {
+        // Right and top edges of the root window are this monitor's
+        assert_eq!(screen([0, 100, 30, 0]).usable_area(), Geometry { x: 1920, y: 30, width: 1820, height: 1050 });
+
+        // Left edge is the other monitor's, so a left strut doesn't reach this one
+        assert_eq!(screen([100, 0, 0, 0]).usable_area(), Geometry { x: 1920, y: 0, width: 1920, height: 1080 });
+    }
+
+    #[test]
+    fn struts_only_from_docks_reaching_monitor() {
+        let mut screen = screen([0; 4]);
+
+        // Top panel along the other monitor only
+        assert!(!screen.set_strut(2, Some([0, 0, 30, 0, 0, 0, 0, 0, 0, 1919, 0, 0])));
+        assert_eq!(screen.struts, [0; 4]);
+
+        // Bottom panel along this one, the largest of each edge counts
+        assert!(screen.set_strut(3, Some([0, 0, 0, 20, 0, 0, 0, 0, 0, 0, 1920, 3839])));
+        assert!(screen.set_strut(4, Some([0, 0, 0, 40, 0, 0, 0, 0, 0, 0, 2000, 2500])));
+        assert_eq!(screen.struts, [0, 0, 0, 40]);
+
+        // Dock gone
+        assert!(screen.set_strut(4, None));
+        assert_eq!(screen.struts, [0, 0, 0, 20]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn legacy_strut_spans_edge() {
+        let mut screen = screen([0; 4]);
+        let strut = screen.strut_from_legacy([0, 0, 30, 0]);
+        assert_eq!(strut, [0, 0, 30, 0, 0, 1079, 0, 1079, 0, 3839, 0, 3839]);
+
+        // Reaches every monitor along the edge
+        assert!(screen.set_strut(3, Some(strut)));
+        assert_eq!(screen.struts, [0, 0, 30, 0]);
+    }
+
+    #[test]
+    fn adjacent_monitor_nearest() {
+        // Two side by side, with a third below the second
+        let monitors = [(0, 0, 1920, 1080), (1920, 0, 1920, 1080), (1920, 1080, 1920, 1080)];
+        assert_eq!(adjacent_monitor(&monitors, 0, Direction::Right), Some(1));
+        assert_eq!(adjacent_monitor(&monitors, 1, Direction::Left), Some(0));
+        assert_eq!(adjacent_monitor(&monitors, 1, Direction::Down), Some(2));
+        assert_eq!(adjacent_monitor(&monitors, 2, Direction::Up), Some(1));
+
+        // Nothing that way
+        assert_eq!(adjacent_monitor(&monitors, 0, Direction::Left), None);
+        assert_eq!(adjacent_monitor(&monitors, 3, Direction::Left), None);
+    }
+}
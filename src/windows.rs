@@ -1,4 +1,4 @@
-use crate::config::{WIN_WIDTH_MIN, WIN_HEIGHT_MIN};
+use crate::config::{Theme, WIN_WIDTH_MIN, WIN_HEIGHT_MIN};
 use crate::helper;
 use crate::screen::Screen;
 use crate::x::{XConn, XWindow, XWindowID};
@@ -15,9 +15,58 @@ fn ensure_in_bounds(val: &mut i32, min: i32, max: i32) {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Geometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
 #[derive(Clone)]
 pub struct Window {
     pub xwindow: XWindow,
+    pub title: String,
+
+    // Class from WM_CLASS, e.g. for IPC clients to match windows against
+    pub class: String,
+
+    pub urgent: bool,
+    pub sticky: bool,
+    pub fullscreen: bool,
+
+    // Kept ontop of other (non-fullscreen) windows, even when they're focused
+    pub above: bool,
+
+    // Floats above the layout even when tiling, e.g. dialogs
+    pub floating: bool,
+
+    // Parent window this is a transient (dialog) for, from WM_TRANSIENT_FOR
+    pub transient_for: Option<XWindowID>,
+
+    // Min / max aspect ratio from WM_NORMAL_HINTS as (numerator, denominator), kept to when resizing
+    pub aspect_min: Option<(u32, u32)>,
+    pub aspect_max: Option<(u32, u32)>,
+
+    // Resize increments and the base size they count from, from WM_NORMAL_HINTS
+    pub resize_inc: Option<(u32, u32)>,
+    pub base_size: (u32, u32),
+
+    // Leftover resize after snapping to increments, carried over to the next resize
+    resize_slack: (i32, i32),
+
+    // Client asked for no decorations via _MOTIF_WM_HINTS, so no border
+    pub borderless: bool,
+
+    // Non-rectangular via the shape extension, a border would spoil the shape
+    pub shaped: bool,
+
+    // Geometry from before going fullscreen, to restore after
+    saved_geometry: Option<Geometry>,
+
+    // Monitors (top, bottom, left, right) to span when fullscreen, from _NET_WM_FULLSCREEN_MONITORS
+    fullscreen_monitors: Option<[usize; 4]>,
+
     protocols: HashSet<xcb::Atom>,
 }
 
@@ -31,41 +80,351 @@ impl From<XWindowID> for Window {
     fn from(window_id: XWindowID) -> Self {
         Self {
             xwindow: XWindow::from(window_id),
+            title: String::new(),
+            class: String::new(),
+            urgent: false,
+            sticky: false,
+            fullscreen: false,
+            above: false,
+            floating: false,
+            transient_for: None,
+            borderless: false,
+            shaped: false,
+            aspect_min: None,
+            aspect_max: None,
+            resize_inc: None,
+            base_size: (0, 0),
+            resize_slack: (0, 0),
+            saved_geometry: None,
+            fullscreen_monitors: None,
             protocols: HashSet::new(),
         }
     }
 }
 
+// Window state read back from the state file, see Window::serialize
+#[derive(PartialEq, Debug)]
+pub struct SavedWindow {
+    pub workspace: usize,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub sticky: bool,
+}
+
 impl Window {
-    pub fn do_resize(&mut self, conn: &XConn, screen: &Screen, dx: i32, dy: i32) {
-        // Iterate current size values
-        self.xwindow.width += dx;
-        self.xwindow.height += dy;
+    pub fn serialize(&self, workspace: usize) -> String {
+        // Line of: "window", id, workspace, x, y, width, height, sticky
+        let xwindow = &self.xwindow;
+        return format!("window {} {} {} {} {} {} {}", xwindow.id, workspace, xwindow.x, xwindow.y, xwindow.width, xwindow.height, self.sticky as u8);
+    }
 
-        // Ensure the window sizes are within set bounds
-        ensure_in_bounds(&mut self.xwindow.width,  WIN_WIDTH_MIN  as i32, screen.xwindow.x + screen.xwindow.width  - self.xwindow.x);
-        ensure_in_bounds(&mut self.xwindow.height, WIN_HEIGHT_MIN as i32, screen.xwindow.y + screen.xwindow.height - self.xwindow.y);
+    pub fn deserialize(line: &str) -> Option<(XWindowID, SavedWindow)> {
+        // Every value must parse, a malformed line is skipped entirely
+        let mut values = line.split_whitespace();
+        if values.next() != Some("window") {
+            return None;
+        }
+        let values: Vec<i64> = values.map(|value| value.parse().ok()).collect::<Option<Vec<i64>>>()?;
+        if values.len() != 7 {
+            return None;
+        }
+        return Some((values[0] as XWindowID, SavedWindow {
+            workspace: values[1] as usize,
+            x: values[2] as i32,
+            y: values[3] as i32,
+            width: values[4] as i32,
+            height: values[5] as i32,
+            sticky: values[6] != 0,
+        }));
+    }
 
-        // Send new window configuration to X
-        conn.configure_window(self.xwindow.id, &helper::values_configure_resize(self.xwindow.width as u32, self.xwindow.height as u32));
+    pub fn resize(&mut self, screen: &Screen, edge_x: i32, edge_y: i32, dx: i32, dy: i32) {
+        // Resize from the given edges, without telling X (see offset). Opposite edges, which stay put when resizing from the left / top
+        let right = self.xwindow.x + self.xwindow.width;
+        let bottom = self.xwindow.y + self.xwindow.height;
+
+        // Resize horizontally from the right or left edge, ensuring within set bounds
+        if edge_x > 0 {
+            self.xwindow.width += dx;
+            ensure_in_bounds(&mut self.xwindow.width, WIN_WIDTH_MIN as i32, screen.xwindow.x + screen.xwindow.width - self.xwindow.x);
+        } else if edge_x < 0 {
+            self.xwindow.width -= dx;
+            ensure_in_bounds(&mut self.xwindow.width, WIN_WIDTH_MIN as i32, right - screen.xwindow.x);
+        }
+
+        // Resize vertically from the bottom or top edge, ensuring within set bounds
+        if edge_y > 0 {
+            self.xwindow.height += dy;
+            ensure_in_bounds(&mut self.xwindow.height, WIN_HEIGHT_MIN as i32, screen.xwindow.y + screen.xwindow.height - self.xwindow.y);
+        } else if edge_y < 0 {
+            self.xwindow.height -= dy;
+            ensure_in_bounds(&mut self.xwindow.height, WIN_HEIGHT_MIN as i32, bottom - screen.xwindow.y);
+        }
+
+        // Snap to resize increments, then keep to aspect ratio following whichever dimension is being dragged
+        self.snap_increments(edge_x != 0, edge_y != 0);
+        self.constrain_aspect(edge_x == 0);
+
+        // Keep opposite edges in place when resizing from the left / top
+        if edge_x < 0 { self.xwindow.x = right - self.xwindow.width; }
+        if edge_y < 0 { self.xwindow.y = bottom - self.xwindow.height; }
+    }
+
+    fn snap_increments(&mut self, snap_width: bool, snap_height: bool) {
+        let (inc_width, inc_height) = match self.resize_inc {
+            Some((inc_width, inc_height)) => (inc_width as i32, inc_height as i32),
+            None => return,
+        };
+        let (base_width, base_height) = (self.base_size.0 as i32, self.base_size.1 as i32);
+
+        // Round down to a whole number of increments over the base size, including any slack
+        // from last time, so slow drags still add up to a full increment
+        if snap_width {
+            let width = self.xwindow.width + self.resize_slack.0;
+            if width > base_width {
+                let snapped = base_width + (width - base_width) / inc_width * inc_width;
+                self.resize_slack.0 = width - snapped;
+                self.xwindow.width = snapped;
+            }
+        }
+        if snap_height {
+            let height = self.xwindow.height + self.resize_slack.1;
+            if height > base_height {
+                let snapped = base_height + (height - base_height) / inc_height * inc_height;
+                self.resize_slack.1 = height - snapped;
+                self.xwindow.height = snapped;
+            }
+        }
+    }
+
+    fn constrain_aspect(&mut self, adjust_width: bool) {
+        // Work in i64 so the cross-multiplying can't overflow
+        let (mut width, mut height) = (self.xwindow.width as i64, self.xwindow.height as i64);
+
+        // Too narrow for min ratio, or too wide for max ratio
+        if let Some((num, den)) = self.aspect_min {
+            let (num, den) = (num as i64, den as i64);
+            if width * den < height * num {
+                if adjust_width { width = height * num / den; } else { height = width * den / num; }
+            }
+        }
+        if let Some((num, den)) = self.aspect_max {
+            let (num, den) = (num as i64, den as i64);
+            if width * den > height * num {
+                if adjust_width { width = height * num / den; } else { height = width * den / num; }
+            }
+        }
+
+        self.xwindow.width = width as i32;
+        self.xwindow.height = height as i32;
+    }
+
+    pub fn resize_edge(&self, x: i32, y: i32) -> (i32, i32) {
+        // Get position relative to window
+        let rel_x = x - self.xwindow.x;
+        let rel_y = y - self.xwindow.y;
+
+        // Nearest edge for each axis, where the middle third resizes neither
+        let edge_x = if rel_x < self.xwindow.width / 3 { -1 } else if rel_x > 2 * self.xwindow.width / 3 { 1 } else { 0 };
+        let edge_y = if rel_y < self.xwindow.height / 3 { -1 } else if rel_y > 2 * self.xwindow.height / 3 { 1 } else { 0 };
+
+        // Clicked dead center, default to bottom-right
+        if edge_x == 0 && edge_y == 0 {
+            return (1, 1);
+        }
+
+        return (edge_x, edge_y);
     }
 
     pub fn do_move(&mut self, conn: &XConn, screen: &Screen, dx: i32, dy: i32) {
         // Iterate current position values
+        self.offset(screen, dx, dy);
+
+        // Send new window configuration to X
+        conn.configure_window(self.xwindow.id, &helper::values_configure_move(self.xwindow.x as u32, self.xwindow.y as u32));
+    }
+
+    pub fn offset(&mut self, screen: &Screen, dx: i32, dy: i32) {
+        // Iterate current position values, without telling X (for batching with other configuration)
         self.xwindow.x += dx;
         self.xwindow.y += dy;
 
         // Ensure the window coords are within set bounds (still pick-up-able)
         ensure_in_bounds(&mut self.xwindow.x, screen.xwindow.x - self.xwindow.width  + MIN_SCREEN_ONSCREEN, screen.xwindow.x + screen.xwindow.width  - MIN_SCREEN_ONSCREEN);
         ensure_in_bounds(&mut self.xwindow.y, screen.xwindow.y - self.xwindow.height + MIN_SCREEN_ONSCREEN, screen.xwindow.y + screen.xwindow.height - MIN_SCREEN_ONSCREEN);
+    }
+
+    pub fn set_geometry(&mut self, conn: &XConn, x: i32, y: i32, width: i32, height: i32) {
+        // Set new geometry values
+        self.xwindow.x = x;
+        self.xwindow.y = y;
+        self.xwindow.width = width;
+        self.xwindow.height = height;
 
         // Send new window configuration to X
-        conn.configure_window(self.xwindow.id, &helper::values_configure_move(self.xwindow.x as u32, self.xwindow.y as u32));
+        conn.configure_window(self.xwindow.id, &helper::values_configure_geometry(x as u32, y as u32, width as u32, height as u32));
+    }
+
+    pub fn border_color(&self, theme: &Theme, focused: bool) -> u32 {
+        // Urgency stands out over focus
+        if self.urgent {
+            return theme.border_urgent;
+        } else if focused {
+            return theme.border_focused;
+        }
+        return theme.border_unfocused;
+    }
+
+    pub fn set_urgent(&mut self, conn: &XConn, theme: &Theme, urgent: bool, focused: bool) {
+        // Nothing to do if unchanged
+        if self.urgent == urgent {
+            return;
+        }
+        self.urgent = urgent;
+
+        // Set border color to reflect urgency, else back to reflecting focus
+        conn.set_border_color(theme, self.xwindow.id, self.border_color(theme, focused));
+
+        // Mirror the state in EWMH
+        self.update_wm_state(conn);
+    }
+
+    pub fn set_sticky(&mut self, conn: &XConn, sticky: bool) {
+        self.sticky = sticky;
+
+        // Mirror the state in EWMH
+        self.update_wm_state(conn);
+    }
+
+    pub fn set_above(&mut self, conn: &XConn, above: bool) {
+        self.above = above;
+
+        // Mirror the state in EWMH
+        self.update_wm_state(conn);
+    }
+
+    pub fn set_fullscreen(&mut self, conn: &XConn, screen: &Screen, theme: &Theme, fullscreen: bool) {
+        // Nothing to do if unchanged
+        if self.fullscreen == fullscreen {
+            return;
+        }
+        self.fullscreen = fullscreen;
+
+        if fullscreen {
+            // Save current geometry, unless already covering the screen (e.g. fullscreen on first map)
+            if (self.xwindow.x, self.xwindow.y, self.xwindow.width, self.xwindow.height) != (screen.xwindow.x, screen.xwindow.y, screen.xwindow.width, screen.xwindow.height) {
+                self.saved_geometry = Some(Geometry { x: self.xwindow.x, y: self.xwindow.y, width: self.xwindow.width, height: self.xwindow.height });
+            }
+
+            // Cover the screen (or requested monitors), without borders, the workspace stacks it ontop
+            conn.set_border_width(theme, self.xwindow.id, 0);
+            let geometry = self.fullscreen_geometry(conn, screen);
+            self.set_geometry(conn, geometry.x, geometry.y, geometry.width, geometry.height);
+        } else {
+            // Restore saved geometry, else center at two thirds of the screen
+            let geometry = self.saved_geometry.take().unwrap_or_else(|| {
+                let (width, height) = (screen.xwindow.width * 2 / 3, screen.xwindow.height * 2 / 3);
+                Geometry {
+                    x: screen.xwindow.x + (screen.xwindow.width - width) / 2,
+                    y: screen.xwindow.y + (screen.xwindow.height - height) / 2,
+                    width: width,
+                    height: height,
+                }
+            });
+
+            // Put borders back, and restore
+            conn.set_border_width(theme, self.xwindow.id, self.border_width(theme));
+            self.set_geometry(conn, geometry.x, geometry.y, geometry.width, geometry.height);
+        }
+
+        // Mirror the state in EWMH
+        self.update_wm_state(conn);
+    }
+
+    pub fn move_to_monitor(&mut self, conn: &XConn, screen: &Screen, dx: i32, dy: i32) {
+        // Keep place relative to the monitor when moved dx, dy to another. Fullscreen windows cover
+        // the new one instead, only moving where they'll be restored to
+        if self.fullscreen {
+            if let Some(geometry) = self.saved_geometry.as_mut() {
+                geometry.x += dx;
+                geometry.y += dy;
+            }
+            let geometry = self.fullscreen_geometry(conn, screen);
+            self.set_geometry(conn, geometry.x, geometry.y, geometry.width, geometry.height);
+            return;
+        }
+        let (x, y, width, height) = (self.xwindow.x + dx, self.xwindow.y + dy, self.xwindow.width, self.xwindow.height);
+        self.set_geometry(conn, x, y, width, height);
+    }
+
+    pub fn set_fullscreen_monitors(&mut self, conn: &XConn, screen: &Screen, monitors: [usize; 4]) {
+        self.fullscreen_monitors = Some(monitors);
+
+        // Mirror the monitors in EWMH
+        let values: Vec<u32> = monitors.iter().map(|monitor| *monitor as u32).collect();
+        conn.set_cardinals(self.xwindow.id, conn.atoms.WM_FULLSCREEN_MONITORS, &values);
+
+        // If already fullscreen, cover the new monitors
+        if self.fullscreen {
+            let geometry = self.fullscreen_geometry(conn, screen);
+            self.set_geometry(conn, geometry.x, geometry.y, geometry.width, geometry.height);
+        }
+    }
+
+    fn fullscreen_geometry(&self, conn: &XConn, screen: &Screen) -> Geometry {
+        // Span the requested monitors' edges if they all exist, else just cover the screen
+        if let Some([top, bottom, left, right]) = self.fullscreen_monitors {
+            let monitors = conn.get_monitors(screen.xwindow.id);
+            if let (Some(top), Some(bottom), Some(left), Some(right)) = (monitors.get(top), monitors.get(bottom), monitors.get(left), monitors.get(right)) {
+                return Geometry { x: left.0, y: top.1, width: right.0 + right.2 - left.0, height: bottom.1 + bottom.3 - top.1 };
+            }
+        }
+        return Geometry { x: screen.xwindow.x, y: screen.xwindow.y, width: screen.xwindow.width, height: screen.xwindow.height };
+    }
+
+    pub fn is_tiled(&self) -> bool {
+        // Fullscreen and floating windows sit outside the tiling layout
+        return !self.fullscreen && !self.floating;
+    }
+
+    pub fn border_width(&self, theme: &Theme) -> u32 {
+        if self.borderless || self.shaped {
+            return 0;
+        }
+        return theme.border_width;
+    }
+
+    pub fn set_borderless(&mut self, conn: &XConn, theme: &Theme, borderless: bool) {
+        // Nothing to do if unchanged
+        if self.borderless == borderless {
+            return;
+        }
+        self.borderless = borderless;
+
+        // Fullscreen windows have no border either way, it's put back on restore
+        if !self.fullscreen {
+            conn.set_border_width(theme, self.xwindow.id, self.border_width(theme));
+        }
+    }
+
+    pub fn update_wm_state(&self, conn: &XConn) {
+        // Build list of current window states
+        let mut states = Vec::new();
+        if self.urgent { states.push(conn.atoms.WM_STATE_DEMANDS_ATTENTION); }
+        if self.sticky { states.push(conn.atoms.WM_STATE_STICKY); }
+        if self.fullscreen { states.push(conn.atoms.WM_STATE_FULLSCREEN); }
+        if self.above { states.push(conn.atoms.WM_STATE_ABOVE); }
+
+        // Set the window state
+        conn.set_wm_state(self.xwindow.id, &states);
     }
 
     pub fn set_supported_protocols(&mut self, conn: &XConn) {
         // Attempt to get wm protocols for window, and add to our
-        // hashset of supported atoms
+        // hashset of supported atoms (cleared, as protocols may since have been dropped)
+        self.protocols.clear();
         if let Some(protocols) = conn.get_wm_protocols(self.xwindow.id) {
             for protocol in protocols {
                 debug!("{}", conn._get_atom_name(protocol));
@@ -74,31 +433,101 @@ impl Window {
         }
     }
 
+    pub fn update_size_hints(&mut self, conn: &XConn) {
+        // Reset, then take what the client has set
+        let hints = conn.get_size_hints(self.xwindow.id);
+        let (aspect_min, aspect_max) = hints.as_ref().map_or((None, None), |hints| hints.aspect());
+        self.aspect_min = aspect_min;
+        self.aspect_max = aspect_max;
+        self.resize_inc = hints.as_ref().and_then(|hints| hints.resize_inc());
+        self.base_size = hints.as_ref().map_or((0, 0), |hints| hints.base_size());
+        self.resize_slack = (0, 0);
+    }
+
     pub fn supports_protocol(&self, atom: &xcb::Atom) -> bool {
         return self.protocols.contains(atom);
     }
 }
 
 #[derive(Default)]
-pub struct Windows(VecDeque<Window>);
+pub struct Windows {
+    // Internal window tracking
+    list: VecDeque<Window>,
+
+    // Index of the currently focused window
+    focus: usize,
+
+    // Window ids by when last focused, most recent last, to fall back on when the focused window goes
+    history: Vec<XWindowID>,
+}
 
 impl Windows {
     pub fn len(&self) -> usize {
-        return self.0.len();
+        return self.list.len();
     }
 
     pub fn is_empty(&self) -> bool {
-        return self.0.len() == 0;
+        return self.list.len() == 0;
     }
 
     pub fn move_front(&mut self, idx: usize) {
         // Only swap with front if window isn't already there
-        if idx != 0 { self.0.swap(0, idx); }
+        if idx != 0 { self.list.swap(0, idx); }
+    }
+
+    pub fn swap(&mut self, a: usize, b: usize) {
+        // Swap window positions
+        self.list.swap(a, b);
+
+        // Ensure focus follows the window, not the position
+        if self.focus == a {
+            self.focus = b;
+        } else if self.focus == b {
+            self.focus = a;
+        }
+    }
+
+    pub fn promote(&mut self, idx: usize) {
+        // Move window to the front, shifting those before it back by one
+        if idx == 0 || idx >= self.list.len() {
+            return;
+        }
+        let window = self.list.remove(idx).unwrap();
+        self.list.push_front(window);
+
+        // Ensure focus follows the window, not the position
+        if self.focus == idx {
+            self.focus = 0;
+        } else if self.focus < idx {
+            self.focus += 1;
+        }
+    }
+
+    pub fn rotate_left(&mut self) {
+        // Front window moves to the back, everything else shifts forward one
+        if self.list.len() < 2 {
+            return;
+        }
+        self.list.rotate_left(1);
+
+        // Ensure focus follows the window, not the position
+        self.focus = (self.focus + self.list.len() - 1) % self.list.len();
+    }
+
+    pub fn rotate_right(&mut self) {
+        // Back window moves to the front, everything else shifts back one
+        if self.list.len() < 2 {
+            return;
+        }
+        self.list.rotate_right(1);
+
+        // Ensure focus follows the window, not the position
+        self.focus = (self.focus + 1) % self.list.len();
     }
 
     pub fn index_of(&self, window_id: XWindowID) -> Option<usize> {
         let mut idx: usize = 0;
-        for window in self.0.iter() {
+        for window in self.list.iter() {
             if window.xwindow.id == window_id {
                 return Some(idx);
             }
@@ -108,32 +537,82 @@ impl Windows {
     }
 
     pub fn add(&mut self, window: Window) {
-        self.0.push_front(window);
+        // New windows are always added to the front and focused
+        self.list.push_front(window);
+        self.focus = 0;
+        self.touch();
+    }
+
+    pub fn add_unfocused(&mut self, window: Window) {
+        // Added to the front, with focus staying on the currently focused window. Least recently focused
+        if !self.list.is_empty() { self.focus += 1; }
+        self.history.insert(0, window.xwindow.id);
+        self.list.push_front(window);
     }
 
     pub fn remove(&mut self, idx: usize) {
-        self.0.remove(idx);
+        // Forget the window ever had focus
+        let was_focused = idx == self.focus;
+        if let Some(window) = self.list.remove(idx) {
+            self.history.retain(|id| *id != window.xwindow.id);
+        }
+
+        // If it had focus, go back to whichever window had it before
+        if was_focused {
+            if let Some(idx) = self.history.last().and_then(|id| self.index_of(*id)) {
+                self.focus = idx;
+                return;
+            }
+        }
+
+        // Shift focus index so it still points at a valid window
+        if self.focus > 0 && (idx < self.focus || self.focus >= self.list.len()) {
+            self.focus -= 1;
+        }
+    }
+
+    pub fn mru(&self) -> Vec<XWindowID> {
+        // Most recently focused first
+        return self.history.iter().rev().copied().collect();
+    }
+
+    pub fn commit_mru(&mut self, mru: &[XWindowID]) {
+        // Put history back as it was before cycling through it, then the focused window goes most recent
+        self.history = mru.iter().rev().copied().filter(|id| self.index_of(*id).is_some()).collect();
+        self.touch();
+    }
+
+    fn touch(&mut self) {
+        // Move focused window to most recent in the history
+        if let Some(window_id) = self.focused().map(|window| window.xwindow.id) {
+            self.history.retain(|id| *id != window_id);
+            self.history.push(window_id);
+        }
     }
 
     pub fn iter(&self) -> impl Iterator<Item = &Window> {
-        return self.0.iter();
+        return self.list.iter();
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Window> {
+        return self.list.iter_mut();
     }
 
     pub fn iter_rev(&self) -> impl Iterator<Item = &Window> {
-        return self.0.iter().rev();
+        return self.list.iter().rev();
     }
 
     pub fn get(&self, idx: usize) -> Option<&Window> {
-        return self.0.get(idx);
+        return self.list.get(idx);
     }
 
     pub fn get_mut(&mut self, idx: usize) -> Option<&mut Window> {
-        return self.0.get_mut(idx);
+        return self.list.get_mut(idx);
     }
 
     pub fn contains(&self, window_id: XWindowID) -> Option<usize> {
         let mut idx: usize = 0;
-        for window in self.0.iter() {
+        for window in self.list.iter() {
             if window.xwindow.id == window_id {
                 return Some(idx);
             }
@@ -149,11 +628,230 @@ impl Windows {
         }
     }
 
+    pub fn set_focused(&mut self, idx: usize) {
+        // Only set if index within bounds
+        if idx < self.list.len() {
+            self.focus = idx;
+            self.touch();
+        }
+    }
+
+    pub fn focused_idx(&self) -> Option<usize> {
+        if self.list.is_empty() {
+            return None;
+        }
+        return Some(self.focus);
+    }
+
     pub fn focused(&self) -> Option<&Window> {
-        return self.0.get(0);
+        return self.list.get(self.focus);
     }
 
     pub fn focused_mut(&mut self) -> Option<&mut Window> {
-        return self.0.get_mut(0);
+        return self.list.get_mut(self.focus);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::THEME;
+
+    fn window(id: XWindowID, width: i32, height: i32) -> Window {
+        let mut window = Window::from(id);
+        window.xwindow.width = width;
+        window.xwindow.height = height;
+        return window;
+    }
+
+    fn windows(ids: &[XWindowID]) -> Windows {
+        // Added in order, so the last is at the front and focused
+        let mut windows = Windows::default();
+        for id in ids {
+            windows.add(Window::from(*id));
+        }
+        return windows;
+    }
+
+    fn ids(windows: &Windows) -> Vec<XWindowID> {
+        return windows.iter().map(|window| window.xwindow.id).collect();
+    }
+
+    fn focused(windows: &Windows) -> Option<XWindowID> {
+        return windows.focused().map(|window| window.xwindow.id);
+    }
+
+    #[test]
+    fn constrain_aspect_over_wide_drag() {
+        let mut window = window(1, 1600, 900);
+        window.aspect_min = Some((16, 9));
+        window.aspect_max = Some((16, 9));
+
+        // Dragged wider, height follows
+        window.xwindow.width = 2000;
+        window.constrain_aspect(false);
+        assert_eq!((window.xwindow.width, window.xwindow.height), (2000, 1125));
+
+        // Dragged taller, width follows
+        window.xwindow.height = 1350;
+        window.constrain_aspect(true);
+        assert_eq!((window.xwindow.width, window.xwindow.height), (2400, 1350));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn constrain_aspect_within_range() {
+        let mut window = window(1, 1000, 800);
+        window.aspect_min = Some((1, 1));
+        window.aspect_max = Some((2, 1));
+
+        // Between square and twice as wide, left alone
+        window.constrain_aspect(false);
+        assert_eq!((window.xwindow.width, window.xwindow.height), (1000, 800));
+
+        // Narrower than square
+        window.xwindow.width = 600;
+        window.constrain_aspect(false);
+        assert_eq!((window.xwindow.width, window.xwindow.height), (600, 600));
+    }
+
+    #[test]
+    fn constrain_aspect_without_hints() {
+        let mut window = window(1, 3000, 100);
+        window.constrain_aspect(false);
+        assert_eq!((window.xwindow.width, window.xwindow.height), (3000, 100));
+    }
+
+    #[test]
+    fn snap_increments_carries_slack() {
+        let mut window = window(1, 35, 45);
+        window.resize_inc = Some((10, 20));
+        window.base_size = (5, 5);
+
+        // Slow drags of 2 each add up to a full increment
+        for _ in 0..4 {
+            window.xwindow.width += 2;
+            window.snap_increments(true, false);
+            assert_eq!(window.xwindow.width, 35);
+        }
+        window.xwindow.width += 2;
+        window.snap_increments(true, false);
+        assert_eq!(window.xwindow.width, 45);
+
+        // Height not being dragged is left alone
+        assert_eq!(window.xwindow.height, 45);
+    }
+
+    #[test]
+    fn promote_keeps_focus() {
+        let mut windows = windows(&[1, 2, 3]);
+        windows.promote(2);
+        assert_eq!(ids(&windows), vec![1, 3, 2]);
+        assert_eq!(focused(&windows), Some(3));
+
+        windows.set_focused(2);
+        windows.promote(2);
+        assert_eq!(ids(&windows), vec![2, 1, 3]);
+        assert_eq!(focused(&windows), Some(2));
+    }
+
+    #[test]
+    fn rotate_keeps_focus() {
+        let mut windows = windows(&[1, 2, 3]);
+        windows.rotate_left();
+        assert_eq!(ids(&windows), vec![2, 1, 3]);
+        assert_eq!(focused(&windows), Some(3));
+
+        windows.rotate_right();
+        assert_eq!(ids(&windows), vec![3, 2, 1]);
+        assert_eq!(focused(&windows), Some(3));
+    }
+
+    #[test]
+    fn remove_focuses_previous() {
+        let mut windows = windows(&[1, 2, 3]);
+        windows.set_focused(1);
+        assert_eq!(focused(&windows), Some(2));
+
+        // Focused goes, back to whichever had focus before
+        windows.remove(1);
+        assert_eq!(ids(&windows), vec![3, 1]);
+        assert_eq!(focused(&windows), Some(3));
+        windows.remove(0);
+        assert_eq!(focused(&windows), Some(1));
+
+        windows.remove(0);
+        assert_eq!(focused(&windows), None);
+    }
+
+    #[test]
+    fn remove_before_focused_keeps_focus() {
+        let mut windows = windows(&[1, 2, 3]);
+        windows.set_focused(2);
+        windows.remove(0);
+        assert_eq!(ids(&windows), vec![2, 1]);
+        assert_eq!(focused(&windows), Some(1));
+    }
+
+    #[test]
+    fn mru_order() {
+        // Most recently focused first
+        let mut windows = windows(&[1, 2, 3]);
+        assert_eq!(windows.mru(), vec![3, 2, 1]);
+        windows.set_focused(2);
+        assert_eq!(windows.mru(), vec![1, 3, 2]);
+
+        // Cycling through doesn't reorder, only where it stops goes first
+        let mru = windows.mru();
+        windows.set_focused(0);
+        windows.set_focused(1);
+        windows.commit_mru(&mru);
+        assert_eq!(windows.mru(), vec![2, 1, 3]);
+
+        // Windows closed while cycling are dropped
+        let mru = windows.mru();
+        windows.remove(0);
+        windows.commit_mru(&mru);
+        assert_eq!(windows.mru(), vec![2, 1]);
+    }
+
+    #[test]
+    fn serialize_round_trip() {
+        let mut window = window(5, 300, 200);
+        window.xwindow.x = 10;
+        window.xwindow.y = -20;
+        window.sticky = true;
+
+        let line = window.serialize(2);
+        assert_eq!(line, "window 5 2 10 -20 300 200 1");
+        assert_eq!(Window::deserialize(&line), Some((5, SavedWindow { workspace: 2, x: 10, y: -20, width: 300, height: 200, sticky: true })));
+    }
+
+    #[test]
+    fn deserialize_rejects_malformed() {
+        assert_eq!(Window::deserialize("window 5 2 10"), None);
+        assert_eq!(Window::deserialize("window 5 2 10 -20 300 200 1 0"), None);
+        assert_eq!(Window::deserialize("window 5 two 10 -20 300 200 1"), None);
+        assert_eq!(Window::deserialize("workspace 5 2 10 -20 300 200 1"), None);
+        assert_eq!(Window::deserialize(""), None);
+    }
+
+    #[test]
+    fn borderless_has_no_border() {
+        let mut window = window(1, 100, 100);
+        assert_eq!(window.border_width(&THEME), THEME.border_width);
+        window.borderless = true;
+        assert_eq!(window.border_width(&THEME), 0);
+    }
+
+    #[test]
+    fn border_color_by_state() {
+        let mut window = window(1, 100, 100);
+        assert_eq!(window.border_color(&THEME, false), THEME.border_unfocused);
+        assert_eq!(window.border_color(&THEME, true), THEME.border_focused);
+
+        // Urgent whether focused or not
+        window.urgent = true;
+        assert_eq!(window.border_color(&THEME, false), THEME.border_urgent);
+        assert_eq!(window.border_color(&THEME, true), THEME.border_urgent);
+    }
+}
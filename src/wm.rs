@@ -1,17 +1,38 @@
-use crate::config::{KEYBINDS, MODKEY};
+use crate::config::{ACTIVE_OPACITY, CHORDS, CHORD_TIMEOUT, DRAG_THROTTLE_TIME, FOCUS_NEW_WINDOWS, INACTIVE_OPACITY, IPC_SOCKET, KEYBINDS, Keybind, MOUSEBINDS, SCRATCHPAD_CLASS, STARTUP_ONCE, STATUS_ROOT_NAME, THEME, Theme, WARP_POINTER_ON_FOCUS, WORKSPACE_KEYBINDS, WORKSPACE_KEYS, run_startup_commands};
 use crate::desktop::Desktop;
+use crate::filter::{FilterAction, FilterChain};
+use crate::layout::{FocusNew, LayoutType};
 use crate::helper;
-use crate::screen::Screen;
-use crate::windows::Window;
+use crate::ipc::{Action, Ipc};
+use crate::screen::{Direction, Screen};
+use crate::windows::{SavedWindow, Window};
 use crate::x::{CursorIndex, XConn, XWindowID};
 
+use std::env;
+use std::fs;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
 use std::process;
-use xcb_util::{cursor, ewmh};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use xcb_util::{cursor, ewmh, keysyms};
+
+// Action performed by a mouse bind
+pub enum MouseAction {
+    // Drag the clicked window
+    Move,
+    // Drag the edge of the clicked window nearest the click
+    Resize,
+    // Momentary, e.g. scrolling
+    Call(fn(&mut WM)),
+}
 
 #[derive(PartialEq)]
 enum MouseMode {
     Ground,
-    Resize,
+    Resize(i32, i32),
     Move,
 }
 
@@ -23,15 +44,92 @@ pub struct WM<'a> {
     pub desktop: Desktop,
     pub screen:  Screen,
 
+    // Look of windows, a reference so the theme can be swapped at runtime
+    pub theme: &'static Theme,
+
+    // Screens of the other monitors, each showing its own workspace. The focused monitor's is screen
+    screens: Vec<Screen>,
+
     // Mouse mode from button press events
     mouse_mode: MouseMode,
     last_mouse_x: i32,
     last_mouse_y: i32,
     selected: Option<XWindowID>,
+
+    // Time of the last drag configure, and pointer position still to apply if throttled since
+    drag_time: Instant,
+    drag_pending: Option<(i32, i32)>,
+
+    // X timestamp of the last user key / button press
+    last_user_time: xcb::Timestamp,
+
+    // Key binds to match next key press against when mid-chord
+    chord: Option<&'static [Keybind]>,
+    chord_time: Instant,
+
+    // Rules deciding whether new windows are managed, floated or left alone
+    filters: FilterChain,
+
+    // Window holding the keyboard modally (e.g. a launcher), getting all key presses, and the
+    // process id of a launcher whose window should take it once mapped
+    modal: Option<XWindowID>,
+    modal_pid: Option<u32>,
+
+    // Window ids most recently focused first, snapshotted while cycling through them, and position in it
+    mru: Option<(Vec<XWindowID>, usize)>,
+
+    // Set by OS signal handlers, signal pipe used to wake the event loop
+    signalled: Arc<AtomicBool>,
+    signal_pipe: UnixStream,
+
+    // Last status written to the root window name
+    status: String,
+
+    // Last workspace and window state written to the state file
+    state: String,
+
+    // IPC socket, if enabled and bound
+    ipc: Option<Ipc>,
+
+    // Event loop running, and whether to re-exec once it stops
+    running: bool,
+    restart: bool,
+
+    // Modifier key standing in for MODKEY in binds
+    modkey: xcb::ModMask,
+}
+
+// Screen of the monitor, else the focused monitor's, e.g. for workspaces not shown on any other
+fn screen_for<'s>(screen: &'s Screen, screens: &'s [Screen], monitor: Option<usize>) -> &'s Screen {
+    return monitor.and_then(|monitor| screens.iter().find(|screen| screen.monitor == monitor)).unwrap_or(screen);
+}
+
+// Whether a window found at startup is ours to manage. Not our own check window nor override redirect ones,
+// and invisible ones only if they were on another workspace
+fn adoptable(window_id: XWindowID, check_id: XWindowID, override_redirect: bool, viewable: bool, desktop: Option<usize>) -> bool {
+    return window_id != check_id && !override_redirect && (viewable || desktop.is_some());
+}
+
+// Pointer movement since the last position, clamped so a bogus event can never throw a window more than
+// the limit, i.e. a screen's width / height. Coordinates are signed i16 from the event so can't wrap anyway
+fn drag_delta(last: i32, to: i32, limit: i32) -> i32 {
+    return to.saturating_sub(last).max(-limit).min(limit);
+}
+
+// Drag the window as the mouse mode has it, returning whether it moved or resized
+fn drag(window: &mut Window, screen: &Screen, mode: &MouseMode, dx: i32, dy: i32) -> bool {
+    match mode {
+        MouseMode::Move => window.offset(screen, dx, dy),
+        MouseMode::Resize(edge_x, edge_y) => window.resize(screen, *edge_x, *edge_y, dx, dy),
+
+        // Spurious motion, e.g. from a passive grab race, nothing to do
+        MouseMode::Ground => return false,
+    }
+    return true;
 }
 
 impl<'a> WM<'a> {
-    pub fn register(conn: &'a ewmh::Connection, screen_idx: i32) -> Self {
+    pub fn register(conn: &'a ewmh::Connection, screen_idx: i32, modkey: xcb::ModMask, workspaces: usize) -> Self {
         // Create new XConn wrapping xcb::Connection
         let mut xconn = XConn::new(conn);
 
@@ -44,45 +142,83 @@ impl<'a> WM<'a> {
         // Try register the root window for necessary window management events
         xconn.change_window_attributes_checked(root_id, &helper::values_attributes_root());
 
-        // Set supported atoms
-        xconn.set_supported(
-            screen_idx,
-            &[
-                xconn.atoms.SUPPORTED,
-                xconn.atoms.WM_PROTOCOLS,
-                xconn.atoms.WM_DELETE_WINDOW,
-            ]
-        );
-
-        // For configured keybinds, register X to grab keys on the root window
-        for (mask, keysym, _) in KEYBINDS {
-            xconn.grab_key(root_id, *mask, *keysym);
-        }
-
-        // Register root window to grab necessary mouse button events
-        xconn.grab_button(root_id, helper::ROOT_BUTTON_GRAB_MASK, xcb::BUTTON_INDEX_1, MODKEY, true);
-        xconn.grab_button(root_id, helper::ROOT_BUTTON_GRAB_MASK, xcb::BUTTON_INDEX_3, MODKEY, true);
-
-        // Create necessary core cursors
-        xconn.create_core_cursor(CursorIndex::LeftPtr, cursor::LEFT_PTR);
+        // Create supporting wm check window, pointing root and itself at it, so we're identified by clients
+        let check_id = xconn.create_check_window(root_id);
+        xconn.set_supporting_wm_check(root_id, check_id);
+        xconn.set_supporting_wm_check(check_id, check_id);
+        xconn.set_wm_name(check_id, env!("CARGO_PKG_NAME"));
+
+
+        // Create necessary cursors, from the cursor theme if supported, else the core cursor font
+        xconn.create_cursors(screen_idx, &[
+            (CursorIndex::LeftPtr,           "left_ptr",            cursor::LEFT_PTR),
+            (CursorIndex::Move,              "fleur",               cursor::FLEUR),
+            (CursorIndex::ResizeTop,         "top_side",            cursor::TOP_SIDE),
+            (CursorIndex::ResizeTopRight,    "top_right_corner",    cursor::TOP_RIGHT_CORNER),
+            (CursorIndex::ResizeRight,       "right_side",          cursor::RIGHT_SIDE),
+            (CursorIndex::ResizeBottomRight, "bottom_right_corner", cursor::BOTTOM_RIGHT_CORNER),
+            (CursorIndex::ResizeBottom,      "bottom_side",         cursor::BOTTOM_SIDE),
+            (CursorIndex::ResizeBottomLeft,  "bottom_left_corner",  cursor::BOTTOM_LEFT_CORNER),
+            (CursorIndex::ResizeLeft,        "left_side",           cursor::LEFT_SIDE),
+            (CursorIndex::ResizeTopLeft,     "top_left_corner",     cursor::TOP_LEFT_CORNER),
+        ]);
 
         // Now set the default starting cursor
         xconn.set_cursor(root_id, CursorIndex::LeftPtr);
 
         // Perform initial screen geometry fetch
-        screen.xwindow.update_geometry(&xconn);
+        screen.update_geometry(&xconn);
+
+        // Register OS signals to set flag and wake event loop
+        let signalled = Arc::new(AtomicBool::new(false));
+        let (signal_pipe, signal_pipe_write) = UnixStream::pair().expect("Creating OS signal pipe");
+        for signal in &[signal_hook::SIGINT, signal_hook::SIGTERM] {
+            signal_hook::flag::register(*signal, Arc::clone(&signalled)).expect("Registering OS signal flag");
+            signal_hook::pipe::register(*signal, signal_pipe_write.try_clone().expect("Cloning OS signal pipe")).expect("Registering OS signal pipe");
+        }
+        debug!("Registered OS signal hooks");
 
         // Create new Self
         let mut new = Self {
             conn: xconn,
-            desktop: Desktop::default(),
+            desktop: Desktop::new(workspaces),
             screen:  screen,
+            theme: &THEME,
+            screens: Vec::new(),
             mouse_mode: MouseMode::Ground,
             last_mouse_x: 0,
             last_mouse_y: 0,
             selected: None,
+            drag_time: Instant::now(),
+            drag_pending: None,
+            last_user_time: 0,
+            chord: None,
+            chord_time: Instant::now(),
+            filters: FilterChain::from_config(),
+            modal: None,
+            modal_pid: None,
+            mru: None,
+            signalled: signalled,
+            signal_pipe: signal_pipe,
+            status: String::new(),
+            state: String::new(),
+            ipc: if IPC_SOCKET { Ipc::bind() } else { None },
+            running: true,
+            restart: false,
+            modkey: modkey,
         };
 
+        // Register X to grab configured keybinds and mouse buttons on the root window
+        new._grab_keys();
+        new._grab_buttons();
+
+        // Restore workspace layouts from before a restart (or crash) before windows get added,
+        // keeping window state to restore as they are
+        let saved = new.desktop.restore(&new.conn, &new.screen, &new._load_state());
+
+        // Show a workspace on each other monitor, so windows adopted into them get laid out there
+        new._update_screens();
+
         // Perform initial client fetch
         for existing_id in new.conn.query_tree(root_id).iter() {
             // Shadow the reference with actual value
@@ -93,14 +229,47 @@ impl<'a> WM<'a> {
             if attr.is_none() { continue; }
             let attr = attr.unwrap();
 
-            // Ignore windows in override redirect mode / invisible
-            if attr.override_redirect() || attr.map_state() as u32 != xcb::MAP_STATE_VIEWABLE {
+            // Workspace the window was on, if we were managing it before a restart. Sticky windows are on all of them
+            let saved_window = saved.get(&existing_id);
+            let desktop = match saved_window {
+                Some(saved_window) if saved_window.sticky => None,
+                Some(saved_window) => Some(saved_window.workspace),
+                None => new.conn.get_wm_desktop(existing_id),
+            };
+            let desktop = desktop.filter(|idx| *idx < new.desktop.max_len());
+
+            // Skip our own check window, override redirect windows, and invisible ones unless they were on another workspace
+            if !adoptable(existing_id, check_id, attr.override_redirect(), attr.map_state() as u32 == xcb::MAP_STATE_VIEWABLE, desktop) {
                 continue;
             }
+
+            // Recreate any dynamic workspace it was on
+            if let Some(idx) = desktop {
+                while idx >= new.desktop.len() && new.desktop.create_workspace(&new.conn, &new.screen).is_some() {}
+            }
             debug!("Adding existing window: {}", existing_id);
 
-            // Map window
-            new._map_window(existing_id);
+            // Map window, or adopt it straight into its previous workspace
+            new._map_window(existing_id, desktop);
+
+            // Restore saved state
+            if let Some(saved_window) = saved_window {
+                new._restore_window(existing_id, saved_window);
+            }
+        }
+
+        // Drop any recreated workspaces left empty, then advertise workspaces
+        new.desktop.cleanup_empty(&new.conn, &new.screen);
+        new.desktop.publish(&new.conn, &new.screen);
+
+        // Finally, advertise all supported EWMH atoms
+        new.conn.set_supported(screen_idx, &new.conn.atoms.supported());
+
+        // Run startup commands, unless restarted in place and they're still running. Not passed on to them either way
+        let restarted = env::var_os(helper::RESTART_ENV).is_some();
+        env::remove_var(helper::RESTART_ENV);
+        if !(STARTUP_ONCE && restarted) {
+            run_startup_commands();
         }
 
         // Return new Self :)
@@ -110,12 +279,47 @@ impl<'a> WM<'a> {
     pub fn run(&mut self) {
         info!("Started running");
 
-        // Perform an initial activation of current workspace in case contains any windows
+        // Perform an initial activation of shown workspaces in case they contain any windows
         self.desktop.current_mut().activate(&self.conn, &self.screen);
+        for (monitor, idx) in self.desktop.visible() {
+            self.desktop.get_mut(idx).activate(&self.conn, screen_for(&self.screen, &self.screens, Some(monitor)));
+        }
+
+        while self.running {
+            // If OS signal received, shutdown cleanly
+            if self.signalled.load(Ordering::SeqCst) {
+                info!("OS signal received");
+                self.shutdown();
+                continue;
+            }
+
+            // Step workspace animation, and apply any throttled drag, if due
+            self.desktop.transition_tick(&self.conn, &self.screen);
+            self._drag_tick();
+
+            // Wake on OS signals and IPC activity
+            let mut wake_fds = vec![self.signal_pipe.as_raw_fd()];
+            if let Some(ipc) = &self.ipc {
+                wake_fds.extend(ipc.fds());
+            }
 
-        loop {
             // Get next event
-            let event = self.conn.next_event();
+            let event = self.conn.next_event(&wake_fds, helper::min_timeout(self.desktop.transition_timeout(), self._drag_timeout()));
+
+            // Handle any IPC clients
+            if let Some(ipc) = &mut self.ipc {
+                for action in ipc.handle(&self.desktop) {
+                    match action {
+                        Action::Rename(name) => self.desktop.rename(&self.conn, &self.screen, &name),
+                    }
+                }
+            }
+
+            // If no event we were woken by something else (or timed out)
+            let event = match event {
+                Some(event) => event,
+                None => continue,
+            };
 
             // Cast (this is unsafe) and pass event to appropriate function.
             //
@@ -123,8 +327,9 @@ impl<'a> WM<'a> {
             // The 8th bit is set if it is a client event which can mess up
             // direct response_type()<=>constant comparisons, hence filtering out the
             // 8th bit value.
+            let response_type = event.response_type() & !0x80;
             unsafe {
-                match event.response_type() & !0x80 {
+                match response_type {
                     // Handle necessary events
                     xcb::CONFIGURE_NOTIFY => self.on_configure_notify(xcb::cast_event(&event)),
                     xcb::CONFIGURE_REQUEST => self.on_configure_request(xcb::cast_event(&event)),
@@ -132,15 +337,75 @@ impl<'a> WM<'a> {
                     xcb::UNMAP_NOTIFY => self.on_unmap_notify(xcb::cast_event(&event)),
                     xcb::DESTROY_NOTIFY => self.on_destroy_notify(xcb::cast_event(&event)),
                     xcb::ENTER_NOTIFY => self.on_enter_notify(xcb::cast_event(&event)),
+                    xcb::FOCUS_IN => self.on_focus_in(xcb::cast_event(&event)),
+                    xcb::FOCUS_OUT => self.on_focus_out(xcb::cast_event(&event)),
+                    xcb::EXPOSE => self.on_expose(xcb::cast_event(&event)),
                     xcb::MOTION_NOTIFY => self.on_motion_notify(xcb::cast_event(&event)),
                     xcb::BUTTON_PRESS => self.on_button_press(xcb::cast_event(&event)),
                     xcb::BUTTON_RELEASE => self.on_button_release(xcb::cast_event(&event)),
                     xcb::KEY_PRESS => self.on_key_press(xcb::cast_event(&event)),
+                    xcb::KEY_RELEASE => self.on_key_release(xcb::cast_event(&event)),
+                    xcb::MAPPING_NOTIFY => self.on_mapping_notify(xcb::cast_event(&event)),
+                    xcb::PROPERTY_NOTIFY => self.on_property_notify(xcb::cast_event(&event)),
                     xcb::CLIENT_MESSAGE => self.on_client_message(xcb::cast_event(&event)),
 
                     unhandled => debug!("unhandled event type: {}", unhandled),
                 }
             }
+
+            // Report any state change to bars
+            self._update_status();
+
+            // Keep state for crash recovery, skipping events that only move the pointer or focus
+            // around or redraw, as saved state can't have changed
+            match response_type {
+                xcb::MOTION_NOTIFY | xcb::ENTER_NOTIFY | xcb::FOCUS_IN | xcb::FOCUS_OUT | xcb::EXPOSE | xcb::KEY_RELEASE => (),
+                _ => self._save_state(),
+            }
+            if let Some(ipc) = &mut self.ipc {
+                ipc.update(&self.desktop);
+            }
+        }
+    }
+
+    fn _save_state(&mut self) {
+        // Only write when something changed
+        let state = self.desktop.serialize();
+        if state == self.state {
+            return;
+        }
+
+        // Write to runtime dir, it's only of use within this X session
+        let path = helper::runtime_path("state");
+        match fs::write(&path, &state) {
+            Ok(_) => debug!("Saved state: {:?}", path),
+            Err(err) => warn!("Saving state {:?}: {}", path, err),
+        }
+        self.state = state;
+    }
+
+    fn _load_state(&self) -> String {
+        // Left by a restart or crash, removed on a clean exit so stale state isn't applied to a later session
+        let path = helper::runtime_path("state");
+        match fs::read_to_string(&path) {
+            Ok(state) => {
+                debug!("Loading state: {:?}", path);
+                return state;
+            },
+            Err(_) => return String::new(),
+        }
+    }
+
+    fn _update_status(&mut self) {
+        if !STATUS_ROOT_NAME {
+            return;
+        }
+
+        // Only write if changed, to save bars redrawing
+        let status = self.desktop.status();
+        if status != self.status {
+            self.conn.set_root_name(self.screen.xwindow.id, &status);
+            self.status = status;
         }
     }
 
@@ -149,96 +414,224 @@ impl<'a> WM<'a> {
         if event.window() == self.screen.xwindow.id {
             debug!("on_configure_notify: root window");
 
-            // Set new root window geometry
-            self.screen.xwindow.x = event.x() as i32;
-            self.screen.xwindow.y = event.y() as i32;
-            self.screen.xwindow.width = event.width() as i32;
-            self.screen.xwindow.height = event.height() as i32;
+            // Finish any workspace animation, as geometry it uses has changed
+            self.desktop.transition_finish(&self.conn, &self.screen);
+
+            // Monitors may have been resized, added or removed
+            self._update_screens();
 
-            // Deactivate / active current workspace to redraw
+            // Deactivate / active shown workspaces to redraw
             self.desktop.current_mut().deactivate(&self.conn);
             self.desktop.current_mut().activate(&self.conn, &self.screen);
+            for (monitor, idx) in self.desktop.visible() {
+                self.desktop.get_mut(idx).deactivate(&self.conn);
+                self.desktop.get_mut(idx).activate(&self.conn, screen_for(&self.screen, &self.screens, Some(monitor)));
+            }
         }
     }
 
     fn on_configure_request(&mut self, event: &xcb::ConfigureRequestEvent) {
-        if let Some((ws, idx)) = self.desktop.contains_mut(event.window()) {
-            debug!("on_configure_request: {}", event.window());
+        debug!("on_configure_request: {}", event.window());
+        self._configure_window(event.window(), event.value_mask(), event.x() as i32, event.y() as i32, event.width() as i32, event.height() as i32);
+    }
 
-            // Get the referenced window at index
-            let window = ws.windows.get_mut(idx).unwrap();
+    fn on_map_request(&mut self, event: &xcb::MapRequestEvent) {
+        if self.desktop.contains(event.window()).is_none() && !self.desktop.scratchpad.contains(event.window()) {
+            debug!("on_map_request: {}", event.window());
 
-            // Value vector we use at end
-            let mut values: Vec<(u16, u32)> = Vec::new();
+            // Window not already tracked! Map!
+            self._map_window(event.window(), None);
+        } else {
+            debug!("on_map_request for already tracked window: {}", event.window());
+        }
+    }
 
-            // If x configuration mask found, push to values vector and set Window geometry
-            if xcb::CONFIG_WINDOW_X as u16 & event.value_mask() != 0 {
-                values.push((xcb::CONFIG_WINDOW_X as u16, event.x() as u32));
-                window.xwindow.x = event.x() as i32;
-            }
+    fn _map_window(&mut self, window_id: XWindowID, desktop: Option<usize>) {
+        // Check whether we ignore it, or float it
+        let action = self.filters.apply(&self.conn, window_id);
+        if action == FilterAction::Ignore {
+            // We don't want to track this (e.g. docks), but we still want it to be displayed
+            debug!("Mapping but NOT tracking window: {}", window_id);
+            self.conn.map_window(window_id);
+
+            // Docks / panels may reserve screen space, follow changes to it and unmapping
+            self.conn.change_window_attributes(window_id, &helper::values_attributes_child_events());
+            self._update_strut(window_id, self._get_strut(window_id));
+            return;
+        }
 
-            // If y configuration mask found, push to values vector and set Window geometry
-            if xcb::CONFIG_WINDOW_Y as u16 & event.value_mask() != 0 {
-                values.push((xcb::CONFIG_WINDOW_Y as u16, event.y() as u32));
-                window.xwindow.y = event.y() as i32;
+        // Create new window
+        let mut window = self._new_window(window_id);
+
+        // If it's the scratchpad and we don't have one yet, capture it instead of adding to a workspace
+        if self.desktop.scratchpad.window.is_none() && self.conn.get_wm_class(window_id).map_or(false, |(instance, class)| instance == SCRATCHPAD_CLASS || class == SCRATCHPAD_CLASS) {
+            self.desktop.scratchpad.capture(&self.conn, &self.screen, window);
+            return;
+        }
+
+        // Floated by a filter, transients (dialogs) centered over the parent it found, anything else
+        // (e.g. dialogs / utilities / splash screens by type) centered on the screen
+        match action {
+            FilterAction::Float(Some(parent_id)) => self._set_transient(&mut window, parent_id),
+            FilterAction::Float(None) => {
+                debug!("Floating window by filter: {}", window_id);
+                window.floating = true;
+                let (width, height) = (window.xwindow.width, window.xwindow.height);
+                let (sx, sy, sw, sh) = (self.screen.xwindow.x, self.screen.xwindow.y, self.screen.xwindow.width, self.screen.xwindow.height);
+                window.set_geometry(&self.conn, sx + (sw - width) / 2, sy + (sh - height) / 2, width, height);
+            },
+            _ => {},
+        }
+
+        // Into the workspace asked for (e.g. its previous one on restart), else the current one. If
+        // that's full, overflow into the next with room. Floating windows don't count
+        let idx = match desktop {
+            Some(idx) => idx,
+            None if window.is_tiled() => self.desktop.index_with_room(&self.conn, &self.screen),
+            None => self.desktop.index(),
+        };
+        self.conn.set_wm_desktop(window_id, idx);
+        if idx != self.desktop.index() {
+            if desktop.is_none() {
+                warn!("Workspace {} full, adding window to workspace {}", self.desktop.index(), idx);
+            }
+            self._adopt_window(window, idx);
+        } else {
+            // Decide whether to focus, and if not, mark urgent so it can still be noticed
+            let focus = self._should_focus_new(window_id);
+            if !focus {
+                debug!("Not focusing new window: {}", window_id);
+                window.set_urgent(&self.conn, self.theme, true, false);
             }
 
-            // If width configuration mask found, push to values vector and set Window geometry
-            if xcb::CONFIG_WINDOW_WIDTH as u16 & event.value_mask() != 0 {
-                values.push((xcb::CONFIG_WINDOW_WIDTH as u16, event.width() as u32));
-                window.xwindow.width = event.width() as i32;
+            // Add the Window to the current workspace
+            self.desktop.current_mut().window_add(&self.conn, &self.screen, window, focus);
+
+            // Spawned as a modal launcher, hand it the keyboard. Matched by process id so other windows
+            // mapped meanwhile, or after the launcher quit without one, aren't taken for it
+            if self.modal_pid.is_some() && self.conn.get_wm_pid(window_id) == self.modal_pid {
+                self.modal_pid = None;
+                self.enter_modal_kbd_grab(window_id);
             }
+        }
 
-            // If height configuration mask found, push to values vector and set Window geometry
-            if xcb::CONFIG_WINDOW_HEIGHT as u16 & event.value_mask() != 0 {
-                values.push((xcb::CONFIG_WINDOW_HEIGHT as u16, event.height() as u32));
-                window.xwindow.height = event.height() as i32;
+        // Honour fullscreen / above requested before mapping, wherever it went
+        let states = self.conn.get_wm_state(window_id);
+        let screen = screen_for(&self.screen, &self.screens, self.desktop.monitor_of(idx));
+        if let Some((ws, idx)) = self.desktop.contains_mut(window_id) {
+            if states.contains(&self.conn.atoms.WM_STATE_FULLSCREEN) {
+                ws.set_fullscreen(&self.conn, screen, self.theme, idx, true);
+            }
+            if states.contains(&self.conn.atoms.WM_STATE_ABOVE) {
+                ws.set_above(&self.conn, idx, true);
             }
+        }
+    }
 
-            // Configure window using filtered values
-            self.conn.configure_window(event.window(), &values);
-        } else {
-            debug!("on_configure_request for untracked window: {}", event.window());
+    fn _should_focus_new(&self, window_id: XWindowID) -> bool {
+        // Nothing to steal focus from
+        if self.desktop.current().windows.is_empty() {
+            return true;
+        }
+
+        match FOCUS_NEW_WINDOWS {
+            FocusNew::Always => return true,
+            FocusNew::Never => return false,
+
+            // User time of 0 means don't focus, else compare (wrapping) with our last input.
+            // No user time at all gives us nothing to go on, so focus as usual
+            FocusNew::Smart => match self.conn.get_wm_user_time(window_id) {
+                Some(0) => return false,
+                Some(time) => return time.wrapping_sub(self.last_user_time) as i32 >= 0,
+                None => return true,
+            },
         }
     }
 
-    fn on_map_request(&mut self, event: &xcb::MapRequestEvent) {
-        if self.desktop.contains(event.window()).is_none() {
-            debug!("on_map_request: {}", event.window());
+    fn _adopt_window(&mut self, window: Window, idx: usize) {
+        // Get window id just the once
+        let window_id = window.xwindow.id;
+        debug!("Adopting window into workspace {}: {}", idx, window_id);
 
-            // Window not already tracked! Map!
-            self._map_window(event.window());
-        } else {
-            debug!("on_map_request for already tracked window: {}", event.window());
+        // Shown on another monitor, so add as usual there, without taking focus
+        if let Some(monitor) = self.desktop.monitor_of(idx) {
+            self.desktop.get_mut(idx).window_add(&self.conn, screen_for(&self.screen, &self.screens, Some(monitor)), window, false);
+            return;
         }
+
+        // Ensure unmapped, as workspace isn't active
+        self.conn.unmap_window(window_id);
+
+        // Start tracking events for this window
+        self.conn.change_window_attributes(window_id, &helper::values_attributes_child_events());
+
+        // Internally add to workspace, it gets mapped on activation
+        self.desktop.get_mut(idx).windows.add(window);
     }
 
-    fn _map_window(&mut self, window_id: XWindowID) {
-        // Try get window types so we can check if we ignore it
-        if let Some(window_type) = self.conn.get_wm_window_type(window_id) {
-            if !(window_type.contains(&self.conn.atoms.WM_WINDOW_TYPE_NORMAL)  ||
-                 window_type.contains(&self.conn.atoms.WM_WINDOW_TYPE_DIALOG)  ||
-                 window_type.contains(&self.conn.atoms.WM_WINDOW_TYPE_TOOLBAR) ||
-                 window_type.contains(&self.conn.atoms.WM_WINDOW_TYPE_UTILITY) ||
-                 window_type.contains(&self.conn.atoms.WM_WINDOW_TYPE_SPLASH)) {
-                // We don't want to track this, but we still want it to be displayed
-                debug!("Mapping but NOT tracking window: {}", window_id);
-                self.conn.map_window(window_id);
-                return;
+    fn _set_transient(&self, window: &mut Window, parent_id: XWindowID) {
+        debug!("Window {} is transient for: {}", window.xwindow.id, parent_id);
+        window.transient_for = Some(parent_id);
+        window.floating = true;
+
+        // Center over parent if we know of it, else the screen
+        let (px, py, pw, ph) = match self.desktop.find_window_by_id(parent_id) {
+            Some(parent) => (parent.xwindow.x, parent.xwindow.y, parent.xwindow.width, parent.xwindow.height),
+            None => (self.screen.xwindow.x, self.screen.xwindow.y, self.screen.xwindow.width, self.screen.xwindow.height),
+        };
+        let (width, height) = (window.xwindow.width, window.xwindow.height);
+        window.set_geometry(&self.conn, px + (pw - width) / 2, py + (ph - height) / 2, width, height);
+    }
+
+    fn _restore_window(&mut self, window_id: XWindowID, saved_window: &SavedWindow) {
+        if let Some((ws, idx)) = self.desktop.contains_mut(window_id) {
+            debug!("Restoring saved state for window: {}", window_id);
+
+            // Geometry only applies when floating, tiling arranges it anyway
+            let floating = ws.layout == LayoutType::Floating;
+            let window = ws.windows.get_mut(idx).unwrap();
+            if floating && !window.fullscreen {
+                window.set_geometry(&self.conn, saved_window.x, saved_window.y, saved_window.width, saved_window.height);
+            }
+
+            // Sticky windows are on all desktops
+            if saved_window.sticky {
+                window.set_sticky(&self.conn, true);
+                self.conn.set_wm_desktop_all(window_id);
             }
         }
+    }
 
+    fn _new_window(&self, window_id: XWindowID) -> Window {
         // Create new window
         let mut window = Window::from(window_id);
 
+        // Have X remap the window should we crash
+        self.conn.change_save_set(window_id, xcb::SET_MODE_INSERT);
+
         // Fetch window geometry
         window.xwindow.update_geometry(&self.conn);
 
         // Get supported protocols
         window.set_supported_protocols(&self.conn);
 
-        // Add the Window to the current workspace
-        self.desktop.current_mut().window_add(&self.conn, &self.screen, window);        
+        // Get size hints, kept up to date on property changes
+        window.update_size_hints(&self.conn);
+
+        // Get title, kept up to date on property changes
+        window.title = self.conn.get_window_title(window_id);
+        window.class = self.conn.get_wm_class(window_id).map_or(String::new(), |(_, class)| class);
+
+        // Skip the border if the client asked for no decorations, or is shaped
+        window.borderless = self.conn.get_motif_decorations(window_id) == Some(false);
+        window.shaped = self.conn.is_shaped(window_id);
+
+        // Set window border, unfocused until we receive FocusIn
+        self.conn.set_border_width(self.theme, window_id, window.border_width(self.theme));
+        self.conn.set_border_color(self.theme, window_id, self.theme.border_unfocused);
+        self._set_opacity(window_id, INACTIVE_OPACITY);
+
+        return window;
     }
 
     fn on_unmap_notify(&mut self, event: &xcb::UnmapNotifyEvent) {
@@ -252,12 +645,58 @@ impl<'a> WM<'a> {
     }
 
     fn _unmap_window(&mut self, window_id: XWindowID) {
+        // Docks aren't tracked, but any space they reserved is freed
+        self._update_strut(window_id, None);
+
+        // Modal window gone, take the keyboard back
+        if self.modal == Some(window_id) {
+            self._exit_modal_kbd_grab();
+        }
+
         // Unmap / destroy event shouldn't be generated by ourselves (we toggle tracking to ensure this).
-        // We can safely assume that we should just remove whatever Window from whatever workspace it may be in
-        if let Some((ws, idx)) = self.desktop.contains_mut(window_id) {
-            ws.window_del(&self.conn, &self.screen, idx, window_id);
-        } else {
+        // We can safely assume that we should just remove whatever Window from wherever it may be
+        let screen = screen_for(&self.screen, &self.screens, self.desktop.monitor_of_window(window_id));
+        if self.desktop.remove_window(&self.conn, screen, window_id).is_none() {
             debug!("on_unmap/destroy_notify for untracked window: {}", window_id);
+            return;
+        }
+        self.conn.change_save_set(window_id, xcb::SET_MODE_DELETE);
+
+        // Stop dragging it if mid move / resize
+        if self.selected == Some(window_id) {
+            self._end_drag();
+        }
+
+        // Transients go with their parent
+        let transients: Vec<XWindowID> = self.desktop.iter()
+            .flat_map(|ws| ws.windows.iter())
+            .filter(|window| window.transient_for == Some(window_id))
+            .map(|window| window.xwindow.id)
+            .collect();
+        for transient_id in transients {
+            debug!("Removing transient of {}: {}", window_id, transient_id);
+            let screen = screen_for(&self.screen, &self.screens, self.desktop.monitor_of_window(transient_id));
+            self.desktop.remove_window(&self.conn, screen, transient_id);
+            self.conn.change_save_set(transient_id, xcb::SET_MODE_DELETE);
+        }
+    }
+
+    fn _get_strut(&self, window_id: XWindowID) -> Option<[i32; 12]> {
+        // Prefer the partial strut, converting the old style if that's all there is
+        return self.conn.get_strut_partial(window_id).or_else(|| self.conn.get_strut(window_id).map(|strut| self.screen.strut_from_legacy(strut)));
+    }
+
+    fn _update_strut(&mut self, window_id: XWindowID, strut: Option<[i32; 12]>) {
+        // If reserved screen space changed, re-arrange to fit what's left. Each monitor keeps its own
+        if self.screen.set_strut(window_id, strut) {
+            self.desktop.current_mut().arrange(&self.conn, &self.screen);
+        }
+        for screen in self.screens.iter_mut() {
+            if screen.set_strut(window_id, strut) {
+                if let Some(idx) = self.desktop.monitor_workspace(screen.monitor) {
+                    self.desktop.get_mut(idx).arrange(&self.conn, screen);
+                }
+            }
         }
     }
 
@@ -268,8 +707,18 @@ impl<'a> WM<'a> {
             return;
         }
 
+        // Modal window keeps the keyboard wherever the pointer goes
+        if self.modal.is_some() {
+            return;
+        }
+
+        // Entering a window shown on another monitor focuses that monitor first
+        if let Some(monitor) = self.desktop.monitor_of_window(event.event()) {
+            self._focus_monitor(monitor);
+        }
+
         // We should only receive these from child windows we've tracked, so if in current workspace we set input focus
-        if self.desktop.current().windows.contains(event.event()).is_some() {
+        if self.desktop.current().windows.contains(event.event()).is_some() || self.desktop.scratchpad.contains(event.event()) {
             debug!("on_enter_notify: {}", event.event());
             self.conn.set_input_focus(event.event());
         } else {
@@ -277,40 +726,191 @@ impl<'a> WM<'a> {
         }
     }
 
+    fn on_focus_in(&mut self, event: &xcb::FocusInEvent) {
+        // Ignore focus changes caused by grabs (e.g. keybinds) or pointer focus
+        if event.mode() as u32 == xcb::NOTIFY_MODE_GRAB || event.mode() as u32 == xcb::NOTIFY_MODE_UNGRAB ||
+           event.detail() as u32 == xcb::NOTIFY_DETAIL_POINTER {
+            return;
+        }
+
+        // Set focused border color on tracked windows
+        if let Some(window) = self.desktop.find_window_by_id_mut(event.event()) {
+            debug!("on_focus_in: {}", event.event());
+
+            // Focused windows are no longer urgent
+            window.set_urgent(&self.conn, self.theme, false, true);
+
+            self.conn.set_border_color(self.theme, event.event(), self.theme.border_focused);
+            self._set_opacity(event.event(), ACTIVE_OPACITY);
+        } else {
+            debug!("on_focus_in for untracked window: {}", event.event());
+        }
+    }
+
+    fn on_focus_out(&mut self, event: &xcb::FocusOutEvent) {
+        // Ignore focus changes caused by grabs (e.g. keybinds) or pointer focus
+        if event.mode() as u32 == xcb::NOTIFY_MODE_GRAB || event.mode() as u32 == xcb::NOTIFY_MODE_UNGRAB ||
+           event.detail() as u32 == xcb::NOTIFY_DETAIL_POINTER {
+            return;
+        }
+
+        // Set unfocused border color on tracked windows
+        if self.desktop.find_window_by_id(event.event()).is_some() {
+            debug!("on_focus_out: {}", event.event());
+            self.conn.set_border_color(self.theme, event.event(), self.theme.border_unfocused);
+            self._set_opacity(event.event(), INACTIVE_OPACITY);
+        } else {
+            debug!("on_focus_out for untracked window: {}", event.event());
+        }
+    }
+
+    fn on_expose(&mut self, event: &xcb::ExposeEvent) {
+        // Only redraw once the last of a series of exposed areas arrives
+        if event.count() != 0 {
+            return;
+        }
+
+        // Borders are all we draw on tracked windows, so repaint in the color for their state.
+        // Only windows on the current workspace hold focus
+        let focused = self.desktop.current().windows.is_focused(event.window());
+        if let Some(window) = self.desktop.find_window_by_id(event.window()) {
+            debug!("on_expose: {}", event.window());
+            self.conn.set_border_color(self.theme, event.window(), window.border_color(self.theme, focused));
+        } else {
+            debug!("on_expose for untracked window: {}", event.window());
+        }
+    }
+
+    fn _configure_window(&mut self, window_id: XWindowID, value_mask: u16, x: i32, y: i32, width: i32, height: i32) {
+        if let Some(window) = self.desktop.find_window_by_id_mut(window_id) {
+            // Value vector we use at end
+            let mut values: Vec<(u16, u32)> = Vec::new();
+
+            // If x configuration mask found, push to values vector and set Window geometry
+            if xcb::CONFIG_WINDOW_X as u16 & value_mask != 0 {
+                values.push((xcb::CONFIG_WINDOW_X as u16, x as u32));
+                window.xwindow.x = x;
+            }
+
+            // If y configuration mask found, push to values vector and set Window geometry
+            if xcb::CONFIG_WINDOW_Y as u16 & value_mask != 0 {
+                values.push((xcb::CONFIG_WINDOW_Y as u16, y as u32));
+                window.xwindow.y = y;
+            }
+
+            // If width configuration mask found, push to values vector and set Window geometry
+            if xcb::CONFIG_WINDOW_WIDTH as u16 & value_mask != 0 {
+                values.push((xcb::CONFIG_WINDOW_WIDTH as u16, width as u32));
+                window.xwindow.width = width;
+            }
+
+            // If height configuration mask found, push to values vector and set Window geometry
+            if xcb::CONFIG_WINDOW_HEIGHT as u16 & value_mask != 0 {
+                values.push((xcb::CONFIG_WINDOW_HEIGHT as u16, height as u32));
+                window.xwindow.height = height;
+            }
+
+            // Configure window using filtered values
+            self.conn.configure_window(window_id, &values);
+        } else {
+            debug!("Configure for untracked window: {}", window_id);
+        }
+    }
+
+    fn _set_opacity(&self, window_id: XWindowID, opacity: f32) {
+        // Fully opaque by default, in which case don't bother
+        if ACTIVE_OPACITY >= 1.0 && INACTIVE_OPACITY >= 1.0 {
+            return;
+        }
+        self.conn.set_cardinals(window_id, self.conn.atoms.WM_WINDOW_OPACITY, &[helper::opacity_to_cardinal(opacity)]);
+    }
+
     fn on_motion_notify(&mut self, event: &xcb::MotionNotifyEvent) {
         // Only perform something if there's a window selected
-        if let Some(selected) = self.selected {
-            debug!("on_motion_notify");
+        if self.selected.is_none() {
+            return;
+        }
+        let (x, y) = (event.root_x() as i32, event.root_y() as i32);
 
-            // Calculate dx, dy
-            let dx = event.root_x() as i32 - self.last_mouse_x;
-            let dy = event.root_y() as i32 - self.last_mouse_y;
+        // If configured too recently, hold onto the position until due
+        if self.drag_time.elapsed() < DRAG_THROTTLE_TIME {
+            self.drag_pending = Some((x, y));
+            return;
+        }
+        self._drag_to(x, y);
+    }
 
-            // Set new last mouse positions
-            self.last_mouse_x = event.root_x() as i32;
-            self.last_mouse_y = event.root_y() as i32;
+    fn _drag_timeout(&self) -> i32 {
+        // Get milliseconds until throttled drag is due, or -1 (no timeout) if none
+        match self.drag_pending {
+            Some(_) => return DRAG_THROTTLE_TIME.checked_sub(self.drag_time.elapsed()).map_or(0, |d| d.as_millis() as i32),
+            None => return -1,
+        }
+    }
 
-            // Get the selected Window, this should be focused but may not always
-            if let Some(idx) = self.desktop.current().windows.contains(selected) {
-                let selected = self.desktop.current_mut().windows.get_mut(idx).unwrap();
+    fn _drag_tick(&mut self) {
+        // Only if throttled drag pending and due
+        if let Some((x, y)) = self.drag_pending {
+            if self.drag_time.elapsed() >= DRAG_THROTTLE_TIME {
+                self._drag_to(x, y);
+            }
+        }
+    }
 
-                // React depending on current MouseMode
-                match self.mouse_mode {
-                    MouseMode::Move => {
-                        selected.do_move(&self.conn, &self.screen, dx, dy);
-                    },
+    fn _drag_to(&mut self, x: i32, y: i32) {
+        // Only perform something if there's a window selected
+        if let Some(selected) = self.selected {
+            debug!("Dragging to: {} {}", x, y);
+
+            // Calculate dx, dy
+            let dx = drag_delta(self.last_mouse_x, x, self.screen.xwindow.width);
+            let dy = drag_delta(self.last_mouse_y, y, self.screen.xwindow.height);
 
-                    MouseMode::Resize => {
-                        selected.do_resize(&self.conn, &self.screen, dx, dy);
-                    },
+            // Set new last mouse positions, and when we configured
+            self.last_mouse_x = x;
+            self.last_mouse_y = y;
+            self.drag_time = Instant::now();
+            self.drag_pending = None;
 
-                    _ => panic!("MouseMode::Ground reached in on_motion_notify()"),
+            // Get the selected Window, this should be focused but may not always
+            if let Some(selected) = self.desktop.find_draggable_mut(selected) {
+                // React depending on current MouseMode, sending the new geometry to X
+                if drag(selected, &self.screen, &self.mouse_mode, dx, dy) {
+                    self.conn.configure_window(selected.xwindow.id, &helper::values_configure_geometry(selected.xwindow.x as u32, selected.xwindow.y as u32, selected.xwindow.width as u32, selected.xwindow.height as u32));
+                } else {
+                    debug!("on_motion_notify: ignoring motion in MouseMode::Ground");
                 }
+            } else {
+                // Window went away mid-drag, e.g. closed or moved off workspace
+                debug!("on_motion_notify: selected window gone, ending drag");
+                self._end_drag();
             }
         }
     }
 
     fn on_button_press(&mut self, event: &xcb::ButtonPressEvent) {
+        // Track user interaction time for focus stealing prevention
+        self.last_user_time = event.time();
+
+        // Find configured mouse bind for this press
+        let press_mask = helper::clean_mask(event.state());
+        let action = match MOUSEBINDS.iter().find(|(mask, button, _)| helper::resolve_modkey(*mask, self.modkey) == press_mask && *button == event.detail() as u32) {
+            Some((_, _, action)) => action,
+            None => return,
+        };
+        debug!("on_button_press: mouse bind {}", event.detail());
+
+        // Clicking a window shown on another monitor focuses that monitor first
+        if let Some(monitor) = self.desktop.monitor_of_window(event.child()) {
+            self._focus_monitor(monitor);
+        }
+
+        // Momentary binds don't need a window, so call and return
+        if let MouseAction::Call(buttonfn) = action {
+            buttonfn(self);
+            return;
+        }
+
         // If button press not in a child window to root, we don't care
         if event.child() == xcb::WINDOW_NONE {
             return;
@@ -323,44 +923,58 @@ impl<'a> WM<'a> {
         self.last_mouse_x = event.root_x() as i32;
         self.last_mouse_y = event.root_y() as i32;
 
-        // Start grabbing pointer
-        self.conn.grab_pointer(self.screen.xwindow.id, helper::ROOT_POINTER_GRAB_MASK);
-
         // If window id different to focused, focus it
         if !self.desktop.current().windows.is_focused(event.child()) {
             self.desktop.current_mut().window_focus(&self.conn, &self.screen, event.child());
         }
 
-        // Get MouseButton for event
-        match event.detail() as u32 {
-            // Left click, set mouse mode
-            xcb::BUTTON_INDEX_1 => {
-                debug!("on_button_press: mouse left click");
+        // Set mouse mode for the bind's action
+        match action {
+            MouseAction::Move => {
+                debug!("on_button_press: mouse move");
                 self.mouse_mode = MouseMode::Move;
             },
 
-            // Right click, set mouse mode
-            xcb::BUTTON_INDEX_3 => {
-                debug!("on_button_press: mouse right click");
-                self.mouse_mode = MouseMode::Resize;
+            // Resize from the edge nearest the click
+            MouseAction::Resize => {
+                debug!("on_button_press: mouse resize");
+                let (edge_x, edge_y) = match self.desktop.current().windows.index_of(event.child()) {
+                    Some(idx) => self.desktop.current().windows.get(idx).unwrap().resize_edge(self.last_mouse_x, self.last_mouse_y),
+                    None => (1, 1),
+                };
+                self.mouse_mode = MouseMode::Resize(edge_x, edge_y);
             },
 
-            _ => panic!("Unhandled button press in on_button_press"),
+            MouseAction::Call(_) => return,
         }
+
+        // Start grabbing pointer, with cursor hinting at the mouse mode
+        let cursor = match self.mouse_mode {
+            MouseMode::Resize(edge_x, edge_y) => CursorIndex::resize(edge_x, edge_y),
+            _ => CursorIndex::Move,
+        };
+        self.conn.grab_pointer(self.screen.xwindow.id, helper::ROOT_POINTER_GRAB_MASK, cursor);
     }
 
     fn on_button_release(&mut self, event: &xcb::ButtonReleaseEvent) {
-        // We only log these in debug builds
-        #[cfg(debug_assertions)]
-        match event.detail() as u32  {
-            xcb::BUTTON_INDEX_1 => debug!("on_button_release: mouse left click"),
-            xcb::BUTTON_INDEX_3 => debug!("on_button_release: mouse right click"),
-            _ => panic!("Unhandled button release in on_button_release"),
+        // Momentary binds and stray releases, nothing to do
+        if self.mouse_mode == MouseMode::Ground {
+            return;
+        }
+        debug!("on_button_release: mouse {}", event.detail());
+
+        // Ensure the window lands where the pointer did, even if the last motion was throttled
+        if self.drag_pending.is_some() {
+            self._drag_to(event.root_x() as i32, event.root_y() as i32);
         }
+        self._end_drag();
+    }
 
+    fn _end_drag(&mut self) {
         // Unselect the window and unset MouseMode
         self.selected = None;
         self.mouse_mode = MouseMode::Ground;
+        self.drag_pending = None;
 
         // Ungrab the pointer
         self.conn.ungrab_pointer();
@@ -371,30 +985,647 @@ impl<'a> WM<'a> {
         let (press_mask, press_key) = self.conn.lookup_keysym(event);
         debug!("on_key_press: {} {}", press_mask, press_key);
 
+        // Track user interaction time for focus stealing prevention
+        self.last_user_time = event.time();
+
+        // Modal window holds the keyboard, our keybinds are released so any press reaching us is stale
+        if self.modal.is_some() {
+            return;
+        }
+
+        // If mid-chord, this key press completes (or aborts) the chord
+        if let Some(chord) = self.chord {
+            self._on_chord_key_press(chord, press_mask, press_key);
+            return;
+        }
+
         // Try get function for keybind
-        for (mask, key, keyfn) in KEYBINDS {
-            // Check for match
-            if *mask == press_mask && *key == press_key {
-                // If window id isn't the focused window id, refocus
-                if !self.desktop.current().windows.is_focused(event.child()) {
-                    self.desktop.current_mut().window_focus(&self.conn, &self.screen, event.child());
+        if let Some(keyfn) = helper::find_bind(KEYBINDS, self.modkey, press_mask, press_key) {
+            // If window id isn't the focused window id, refocus. Not mid-cycle, where focus is ours to move
+            if self.mru.is_none() && !self.desktop.current().windows.is_focused(event.child()) {
+                self.desktop.current_mut().window_focus(&self.conn, &self.screen, event.child());
+            }
+
+            // Execute! And return
+            keyfn(self);
+            return;
+        }
+
+        // Try per-workspace keybinds, called with the workspace index
+        if let Some(idx) = WORKSPACE_KEYS.iter().take(self.desktop.count()).position(|key| *key == press_key) {
+            for (mask, keyfn) in WORKSPACE_KEYBINDS {
+                if helper::resolve_modkey(*mask, self.modkey) == press_mask {
+                    keyfn(self, idx);
+                    return;
                 }
+            }
+        }
+
+        // Check for chord prefix
+        if let Some(keybinds) = helper::find_bind(CHORDS, self.modkey, press_mask, press_key) {
+            // Grab keyboard so we receive the following key press
+            if self.conn.grab_keyboard(self.screen.xwindow.id) {
+                debug!("Starting key chord");
+                self.chord = Some(keybinds);
+                self.chord_time = Instant::now();
+            }
+        }
+    }
+
+    fn _on_chord_key_press(&mut self, chord: &'static [Keybind], press_mask: xcb::ModMask, press_key: xcb::Keysym) {
+        // If timed out, abort the chord
+        if self.chord_time.elapsed() > CHORD_TIMEOUT {
+            debug!("Key chord timed out");
+            self._end_chord();
+            return;
+        }
+
+        // Modifier key presses are part of the next key, keep waiting
+        if keysyms::is_modifier_key(press_key) {
+            return;
+        }
+
+        // Chord is over either way
+        self._end_chord();
+
+        // Try get function for chord keybind
+        match helper::find_bind(chord, self.modkey, press_mask, press_key) {
+            Some(keyfn) => keyfn(self),
+            None => debug!("Unknown key chord, aborting"),
+        }
+    }
+
+    fn on_key_release(&mut self, event: &xcb::KeyReleaseEvent) {
+        // Only of interest while cycling, which we grab the keyboard for
+        if self.mru.is_none() {
+            return;
+        }
+
+        // Releasing the modkey commits the cycle. State is from before the release
+        let (release_mask, release_key) = self.conn.lookup_keysym(event);
+        debug!("on_key_release: {} {}", release_mask, release_key);
+        if keysyms::is_modifier_key(release_key) && release_mask & self.modkey != 0 {
+            self._end_mru_cycle();
+        }
+    }
 
-                // Execute! And return
-                keyfn(self);
+    pub fn mru_cycle(&mut self) {
+        // Start cycling, grabbing the keyboard so we see the modkey released
+        if self.mru.is_none() {
+            let mru = self.desktop.current().windows.mru();
+            if mru.len() < 2 || !self.conn.grab_keyboard(self.screen.xwindow.id) {
                 return;
             }
+            debug!("Starting MRU cycle");
+            self.mru = Some((mru, 0));
+        }
+
+        // Focus next most recently used window
+        if let Some((mru, pos)) = self.mru.as_mut() {
+            *pos = (*pos + 1) % mru.len();
+            let window_id = mru[*pos];
+            self.desktop.current_mut().window_focus(&self.conn, &self.screen, window_id);
+        }
+    }
+
+    fn _end_mru_cycle(&mut self) {
+        debug!("Ending MRU cycle");
+
+        // Only the window settled on counts as used, not those passed over
+        if let Some((mru, _)) = self.mru.take() {
+            self.desktop.current_mut().windows.commit_mru(&mru);
+        }
+        self.conn.ungrab_keyboard();
+    }
+
+    pub fn expect_modal(&mut self, pid: u32) {
+        // Window the process maps takes the keyboard, see run_modal in config
+        self.modal_pid = Some(pid);
+    }
+
+    pub fn enter_modal_kbd_grab(&mut self, window_id: XWindowID) {
+        // Abandon any chord, it'd otherwise release our grab
+        if self.chord.is_some() {
+            self._end_chord();
+        }
+
+        // Release our keybinds and focus the window, so it gets the real key events. It's then
+        // free to grab the keyboard itself, as dmenu-style launchers do
+        debug!("Entering modal keyboard grab for window: {}", window_id);
+        self.modal = Some(window_id);
+        self.conn.ungrab_all_keys(self.screen.xwindow.id);
+        self.conn.set_input_focus(window_id);
+    }
+
+    fn _exit_modal_kbd_grab(&mut self) {
+        // Take our keybinds back
+        debug!("Exiting modal keyboard grab");
+        self.modal = None;
+        self._grab_keys();
+    }
+
+    fn _end_chord(&mut self) {
+        // Unset chord and release the keyboard
+        self.chord = None;
+        self.conn.ungrab_keyboard();
+    }
+
+    fn on_mapping_notify(&mut self, event: &xcb::MappingNotifyEvent) {
+        // We only care about keyboard mapping changes, pointer / modifier changes don't affect our keycodes
+        if event.request() as u32 != xcb::MAPPING_KEYBOARD {
+            return;
+        }
+        debug!("on_mapping_notify: keyboard");
+
+        // Update keyboard mapping so keysyms resolve to the new keycodes
+        self.conn.refresh_keyboard_mapping(event);
+
+        // Ungrab the stale keycodes, then grab again using new mapping
+        self._regrab_keys();
+    }
+
+    fn _regrab_keys(&self) {
+        // Keybinds stay released while a modal window holds the keyboard, they're grabbed again on exit
+        self.conn.ungrab_all_keys(self.screen.xwindow.id);
+        if self.modal.is_none() {
+            self._grab_keys();
+        }
+    }
+
+    fn _grab_keys(&self) {
+        // For configured keybinds, register X to grab keys on the root window
+        for (mask, keysym, _) in KEYBINDS {
+            self.conn.grab_key(self.screen.xwindow.id, helper::resolve_modkey(*mask, self.modkey), *keysym);
+        }
+
+        // Likewise for key chord prefixes
+        for (mask, keysym, _) in CHORDS {
+            self.conn.grab_key(self.screen.xwindow.id, helper::resolve_modkey(*mask, self.modkey), *keysym);
+        }
+
+        // And per-workspace keybinds, for each workspace there's a key for
+        for keysym in WORKSPACE_KEYS.iter().take(self.desktop.count()) {
+            for (mask, _) in WORKSPACE_KEYBINDS {
+                self.conn.grab_key(self.screen.xwindow.id, helper::resolve_modkey(*mask, self.modkey), *keysym);
+            }
+        }
+    }
+
+    fn _grab_buttons(&self) {
+        // Register root window to grab configured mouse binds
+        for (mask, button, _) in MOUSEBINDS {
+            self.conn.grab_button(self.screen.xwindow.id, helper::ROOT_BUTTON_GRAB_MASK, *button, helper::resolve_modkey(*mask, self.modkey), true);
+        }
+    }
+
+    fn on_property_notify(&mut self, event: &xcb::PropertyNotifyEvent) {
+        if event.atom() == xcb::ATOM_WM_HINTS {
+            // Check if focused before borrowing the workspace
+            let focused = self.desktop.current().windows.is_focused(event.window());
+
+            if let Some(window) = self.desktop.find_window_by_id_mut(event.window()) {
+                debug!("on_property_notify: WM_HINTS {}", event.window());
+
+                // Update urgency from hints. The focused window is never marked urgent
+                let urgent = !focused && self.conn.get_urgency(event.window());
+                window.set_urgent(&self.conn, self.theme, urgent, focused);
+            } else {
+                debug!("on_property_notify for untracked window: {}", event.window());
+            }
+        } else if event.atom() == xcb::ATOM_WM_NORMAL_HINTS {
+            if let Some(window) = self.desktop.find_window_by_id_mut(event.window()) {
+                debug!("on_property_notify: WM_NORMAL_HINTS {}", event.window());
+                window.update_size_hints(&self.conn);
+            }
+        } else if event.atom() == xcb::ATOM_WM_NAME || event.atom() == self.conn.atoms.WM_NAME {
+            if let Some(window) = self.desktop.find_window_by_id_mut(event.window()) {
+                debug!("on_property_notify: title {}", event.window());
+                window.title = self.conn.get_window_title(event.window());
+            }
+        } else if event.atom() == self.conn.atoms.WM_STRUT || event.atom() == self.conn.atoms.WM_STRUT_PARTIAL {
+            debug!("on_property_notify: strut {}", event.window());
+            self._update_strut(event.window(), self._get_strut(event.window()));
+        } else if event.atom() == self.conn.atoms.WM_PROTOCOLS {
+            if let Some(window) = self.desktop.find_window_by_id_mut(event.window()) {
+                debug!("on_property_notify: WM_PROTOCOLS {}", event.window());
+                window.set_supported_protocols(&self.conn);
+            }
+        } else if event.atom() == self.conn.atoms.MOTIF_WM_HINTS {
+            let screen = screen_for(&self.screen, &self.screens, self.desktop.monitor_of_window(event.window()));
+            if let Some((ws, idx)) = self.desktop.contains_mut(event.window()) {
+                debug!("on_property_notify: _MOTIF_WM_HINTS {}", event.window());
+
+                // Update border from the new hints
+                let borderless = self.conn.get_motif_decorations(event.window()) == Some(false);
+                ws.set_borderless(&self.conn, screen, self.theme, idx, borderless);
+            } else {
+                debug!("on_property_notify for untracked window: {}", event.window());
+            }
         }
     }
 
     fn on_client_message(&mut self, event: &xcb::ClientMessageEvent) {
         debug!("on_client_message: {} {}", event.window(), self.conn._get_atom_name(event.type_()));
+
+        // Message data, layout depends on the message type
+        let data = event.data().data32();
+
+        if event.type_() == self.conn.atoms.WM_STATE {
+            // Data is (action, first property, second property, source)
+            let (fullscreen_atom, above_atom, attention_atom) = (self.conn.atoms.WM_STATE_FULLSCREEN, self.conn.atoms.WM_STATE_ABOVE, self.conn.atoms.WM_STATE_DEMANDS_ATTENTION);
+            let screen = screen_for(&self.screen, &self.screens, self.desktop.monitor_of_window(event.window()));
+            if let Some((ws, idx)) = self.desktop.contains_mut(event.window()) {
+                // Action is one of remove, add, toggle
+                let apply = |current: bool| match data[0] {
+                    0 => Some(false),
+                    1 => Some(true),
+                    2 => Some(!current),
+                    _ => None,
+                };
+
+                if data[1] == fullscreen_atom || data[2] == fullscreen_atom {
+                    if let Some(fullscreen) = apply(ws.windows.get(idx).unwrap().fullscreen) {
+                        ws.set_fullscreen(&self.conn, screen, self.theme, idx, fullscreen);
+                    }
+                }
+                if data[1] == above_atom || data[2] == above_atom {
+                    if let Some(above) = apply(ws.windows.get(idx).unwrap().above) {
+                        ws.set_above(&self.conn, idx, above);
+                    }
+                }
+                if data[1] == attention_atom || data[2] == attention_atom {
+                    if let Some(urgent) = apply(ws.windows.get(idx).unwrap().urgent) {
+                        self.conn.set_urgency(event.window(), urgent);
+                    }
+                }
+            }
+        } else if event.type_() == self.conn.atoms.WM_FULLSCREEN_MONITORS {
+            // Data is (top, bottom, left, right, source) monitor indices
+            let screen = screen_for(&self.screen, &self.screens, self.desktop.monitor_of_window(event.window()));
+            if let Some((ws, idx)) = self.desktop.contains_mut(event.window()) {
+                let monitors = [data[0] as usize, data[1] as usize, data[2] as usize, data[3] as usize];
+                ws.set_fullscreen_monitors(&self.conn, screen, idx, monitors);
+            }
+        } else if event.type_() == self.conn.atoms.CLOSE_WINDOW {
+            // Close as if by keybind, politely if supported
+            if let Some(window) = self.desktop.find_window_by_id(event.window()) {
+                self.conn.destroy_window(window);
+            }
+        } else if event.type_() == self.conn.atoms.RESTACK_WINDOW {
+            // Data is (source, sibling, detail)
+            let (ws, _) = match self.desktop.contains_mut(event.window()) {
+                Some(found) => found,
+                None => return,
+            };
+
+            // Go through the workspace's stack so it stays in step with X, and layers still apply.
+            // We don't track what overlaps what, so TopIf / Opposite raise and BottomIf lowers
+            let above = data[2] != xcb::STACK_MODE_BELOW && data[2] != xcb::STACK_MODE_BOTTOM_IF;
+            if data[1] != xcb::NONE {
+                ws.restack_sibling(&self.conn, event.window(), data[1], above);
+            } else if above {
+                ws.raise(&self.conn, event.window());
+            } else {
+                ws.lower(&self.conn, event.window());
+            }
+        } else if event.type_() == self.conn.atoms.MOVERESIZE_WINDOW {
+            // Data is (gravity and flags, x, y, width, height). Flag bits 8-11 mark
+            // which of x, y, width, height are set, same order as the configure mask
+            let value_mask = ((data[0] >> 8) & 0xF) as u16;
+            self._configure_window(event.window(), value_mask, data[1] as i32, data[2] as i32, data[3] as i32, data[4] as i32);
+        }
+    }
+
+    pub fn shutdown(&mut self) {
+        info!("Shutting down");
+
+        // Save state, restored if we're restarted. It's only for crashes otherwise
+        if self.restart {
+            self._save_state();
+        } else {
+            let _ = fs::remove_file(helper::runtime_path("state"));
+        }
+
+        // Stop tracking events for all windows
+        for ws in self.desktop.iter() {
+            for window in ws.windows.iter() {
+                self.conn.change_window_attributes(window.xwindow.id, &helper::values_attributes_no_events());
+            }
+        }
+
+        // Show the scratchpad so it isn't left hidden without a window manager
+        self.desktop.scratchpad.show(&self.conn);
+        if let Some(window) = &self.desktop.scratchpad.window {
+            self.conn.change_window_attributes(window.xwindow.id, &helper::values_attributes_no_events());
+        }
+
+        // Release the pointer if mid move / resize
+        if self.mouse_mode != MouseMode::Ground {
+            self.conn.ungrab_pointer();
+        }
+
+        // Ungrab all keys and buttons on the root window
+        self.conn.ungrab_all_keys(self.screen.xwindow.id);
+        self.conn.ungrab_all_buttons(self.screen.xwindow.id);
+
+        // Set root window cursor back to default
+        self.conn.change_window_attributes(self.screen.xwindow.id, &helper::values_attributes_cursor(xcb::NONE));
+
+        // Ensure all the above reaches X before we stop
+        self.conn.flush();
+
+        // Close IPC socket, removing it
+        self.ipc = None;
+
+        // Stop the event loop
+        self.running = false;
+    }
+
+    pub fn add_workspace(&mut self) {
+        // Go straight to it, its workspace keys grabbed if it has any
+        if let Some(idx) = self.desktop.add_workspace(&self.conn, &self.screen) {
+            self.goto(idx);
+            self._regrab_keys();
+        }
+    }
+
+    pub fn remove_workspace(&mut self) {
+        // Workspace keys past the new count no longer apply
+        if self.desktop.remove_workspace(&self.conn, &self.screen) {
+            self._regrab_keys();
+        }
+    }
+
+    pub fn goto(&mut self, idx: usize) {
+        // Shown on another monitor, so focus that rather than taking it away from there
+        match self.desktop.monitor_of(idx) {
+            Some(monitor) => {
+                self._focus_monitor(monitor);
+                self._warp_to_monitor();
+            },
+            None => self.desktop.goto(&self.conn, &self.screen, idx),
+        }
+    }
+
+    pub fn goto_prev(&mut self) {
+        // Goto previously active workspace
+        self.goto(self.desktop.index_prev_active());
+    }
+
+    pub fn goto_urgent(&mut self) -> bool {
+        let idx = match self.desktop.index_urgent() {
+            Some(idx) => idx,
+            None => return false,
+        };
+        debug!("Going to urgent window in workspace: {}", idx);
+
+        // Goto the workspace and focus the urgent window (clearing urgency on FocusIn)
+        self.goto(idx);
+        let window_idx = self.desktop.current().windows.iter().position(|window| window.urgent).unwrap();
+        self.desktop.current_mut().window_focus_idx(&self.conn, &self.screen, window_idx);
+        return true;
+    }
+
+    pub fn send_window_to_workspace(&mut self, idx: usize) {
+        // Nothing to do if already there, or no such workspace
+        if idx == self.desktop.index() || idx >= self.desktop.len() {
+            return;
+        }
+
+        // Remove the focused window from the current workspace, if any
+        let mut focused = match self.desktop.current_mut().window_del_focused(&self.conn, &self.screen) {
+            Some(focused) => focused,
+            None => return,
+        };
+        self.conn.set_wm_desktop(focused.xwindow.id, idx);
+
+        // Floating windows keep their place relative to the monitor the workspace is shown on, else
+        // last shown on. Tiled get laid out there
+        let monitor = self.desktop.monitor_of(idx);
+        let screen = screen_for(&self.screen, &self.screens, monitor);
+        let origin = if monitor.is_some() { Some((screen.xwindow.x, screen.xwindow.y)) } else { self.desktop.get(idx).origin() };
+        if let Some((x, y)) = origin {
+            if self.desktop.get(idx).layout == LayoutType::Floating || !focused.is_tiled() {
+                focused.move_to_monitor(&self.conn, screen, x - self.screen.xwindow.x, y - self.screen.xwindow.y);
+            }
+        }
+
+        // Shown on another monitor, so add as usual there, else just track it until shown
+        if monitor.is_some() {
+            self.desktop.get_mut(idx).window_add(&self.conn, screen, focused, false);
+        } else {
+            self.desktop.get_mut(idx).windows.add(focused);
+        }
+    }
+
+    pub fn focus_monitor(&mut self, direction: Direction) {
+        // Nothing to do without a monitor that way
+        if let Some(monitor) = self.screen.adjacent_monitor(&self.conn, direction) {
+            debug!("Focusing monitor {} {:?} of {}", monitor, direction, self.screen.monitor);
+            self._focus_monitor(monitor);
+            self._warp_to_monitor();
+        }
+    }
+
+    pub fn focus_next_monitor(&mut self) {
+        // Nothing to do with a single monitor
+        if let Some(monitor) = self.screen.next_monitor(&self.conn) {
+            debug!("Focusing next monitor {} of {}", monitor, self.screen.monitor);
+            self._focus_monitor(monitor);
+            self._warp_to_monitor();
+        }
+    }
+
+    fn _focus_monitor(&mut self, monitor: usize) {
+        // Nothing to do without a screen there, e.g. more monitors than workspaces
+        let pos = match self.screens.iter().position(|screen| screen.monitor == monitor) {
+            Some(pos) => pos,
+            None => return,
+        };
+
+        // Swap in its screen, the workspace shown there becoming current
+        mem::swap(&mut self.screen, self.screens.get_mut(pos).unwrap());
+        self.desktop.focus_monitor(&self.conn, &self.screen, self.screens.get(pos).unwrap().monitor, monitor);
+
+        // Focus the window focused there. If none, keyboard input goes to the root window rather than staying behind
+        match self.desktop.current().windows.focused() {
+            Some(focused) => self.conn.set_input_focus(focused.xwindow.id),
+            None => self.conn.set_input_focus(self.screen.xwindow.id),
+        }
+    }
+
+    fn _warp_to_monitor(&self) {
+        // Follow with the pointer if configured, onto the focused window if any so focus-follows-mouse keeps it
+        if WARP_POINTER_ON_FOCUS {
+            match self.desktop.current().windows.focused() {
+                Some(focused) => {
+                    let (x, y) = focused.xwindow.center();
+                    self.conn.warp_pointer(focused.xwindow.id, x, y);
+                },
+                None => {
+                    let (x, y) = self.screen.xwindow.center();
+                    self.conn.warp_pointer(self.screen.xwindow.id, self.screen.xwindow.x + x, self.screen.xwindow.y + y);
+                },
+            }
+        }
+    }
+
+    fn _update_screens(&mut self) {
+        // At least the one monitor, covering the root window if there's no way of knowing monitors
+        let len = self.conn.get_monitors(self.screen.xwindow.id).len().max(1);
+
+        // Focused monitor gone, focus the first instead
+        if self.screen.monitor >= len {
+            self._focus_monitor(0);
+        }
+
+        // Monitors gone, their workspaces are hidden
+        for screen in self.screens.iter().filter(|screen| screen.monitor >= len) {
+            self.desktop.hide_monitor(&self.conn, &self.screen, screen.monitor);
+        }
+        self.screens.retain(|screen| screen.monitor < len);
+
+        // Monitors still here may have moved or been resized
+        self.screen.update_geometry(&self.conn);
+        for screen in self.screens.iter_mut() {
+            screen.update_geometry(&self.conn);
+        }
+
+        // New monitors each show a workspace not shown elsewhere, while there are any
+        for monitor in 0..len {
+            if monitor == self.screen.monitor || self.screens.iter().any(|screen| screen.monitor == monitor) {
+                continue;
+            }
+            let screen = self.screen.for_monitor(&self.conn, monitor);
+            if !self.desktop.show_on_monitor(&self.conn, &screen, monitor) {
+                warn!("No workspace left to show on monitor: {}", monitor);
+                break;
+            }
+            self.screens.push(screen);
+        }
+    }
+
+    pub fn move_window_to_monitor(&mut self, direction: Direction) {
+        // Nothing to do without a monitor that way
+        if let Some(monitor) = self.screen.adjacent_monitor(&self.conn, direction) {
+            self._move_window_to_monitor(monitor);
+        }
+    }
+
+    pub fn move_window_to_next_monitor(&mut self) {
+        // Nothing to do with a single monitor
+        if let Some(monitor) = self.screen.next_monitor(&self.conn) {
+            self._move_window_to_monitor(monitor);
+        }
+    }
+
+    fn _move_window_to_monitor(&mut self, monitor: usize) {
+        // Send the focused window to the workspace shown there
+        let idx = match self.desktop.monitor_workspace(monitor) {
+            Some(idx) => idx,
+            None => return,
+        };
+        let window_id = match self.desktop.current().windows.focused() {
+            Some(focused) => focused.xwindow.id,
+            None => return,
+        };
+        debug!("Moving window {} to monitor {} workspace {}", window_id, monitor, idx);
+        self.send_window_to_workspace(idx);
+
+        // Follow it, focusing it there
+        self._focus_monitor(monitor);
+        self.desktop.current_mut().window_focus(&self.conn, &self.screen, window_id);
+        self._warp_to_monitor();
+    }
+
+    pub fn toggle_above(&mut self) {
+        // Keep the focused window ontop, or let it back down
+        self.desktop.current_mut().toggle_above_focused(&self.conn);
+    }
+
+    pub fn quit(&mut self) {
+        info!("Quitting");
+        self.shutdown();
+    }
+
+    pub fn restart(&mut self) {
+        info!("Restarting");
+
+        // Shutdown cleanly, main re-execs us once the event loop stops
+        self.restart = true;
+        self.shutdown();
+    }
+
+    pub fn restarting(&self) -> bool {
+        return self.restart;
     }
 
     pub fn kill(&mut self) {
         info!("Killing");
 
+        // Release our grabs at least, so another window manager / key grabber can start straight away
+        self.conn.ungrab_all_keys(self.screen.xwindow.id);
+        self.conn.ungrab_all_buttons(self.screen.xwindow.id);
+        self.conn.flush();
+
         // Kill via standard exit
         process::exit(0);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen() -> Screen {
+        let mut screen = Screen::new(0, 1);
+        screen.xwindow.width = 1920;
+        screen.xwindow.height = 1080;
+        return screen;
+    }
+
+    fn window(id: XWindowID) -> Window {
+        let mut window = Window::from(id);
+        window.xwindow.x = 100;
+        window.xwindow.y = 100;
+        window.xwindow.width = 400;
+        window.xwindow.height = 300;
+        return window;
+    }
+
+    #[test]
+    fn adopt_existing_windows() {
+        assert!(adoptable(2, 1, false, true, None));
+
+        // Never our own check window, nor override redirect windows
+        assert!(!adoptable(1, 1, false, true, None));
+        assert!(!adoptable(2, 1, true, true, Some(0)));
+
+        // Invisible, only if it was on another workspace
+        assert!(!adoptable(2, 1, false, false, None));
+        assert!(adoptable(2, 1, false, false, Some(3)));
+    }
+
+    #[test]
+    fn drag_by_mouse_mode() {
+        let (screen, mut window) = (screen(), window(2));
+        assert!(drag(&mut window, &screen, &MouseMode::Move, 10, -20));
+        assert_eq!((window.xwindow.x, window.xwindow.y), (110, 80));
+        assert!(drag(&mut window, &screen, &MouseMode::Resize(1, 1), 50, 40));
+        assert_eq!((window.xwindow.width, window.xwindow.height), (450, 340));
+
+        // Motion without a drag in progress leaves the window be
+        assert!(!drag(&mut window, &screen, &MouseMode::Ground, 10, 10));
+        assert_eq!((window.xwindow.x, window.xwindow.y, window.xwindow.width, window.xwindow.height), (110, 80, 450, 340));
+    }
+
+    #[test]
+    fn drag_delta_clamped() {
+        assert_eq!(drag_delta(100, 130, 1920), 30);
+        assert_eq!(drag_delta(100, 70, 1920), -30);
+
+        // Never more than the limit either way
+        assert_eq!(drag_delta(0, 5000, 1920), 1920);
+        assert_eq!(drag_delta(i32::MAX, i32::MIN, 1080), -1080);
+    }
+}
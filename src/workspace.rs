@@ -1,15 +1,45 @@
-use crate::layout::{floating, LayoutType};
+use crate::config::{AUTO_FULLSCREEN_SINGLE, BSP_RATIO_STEP, MASTER_COUNT, MASTER_FACTOR, MASTER_FACTOR_MAX, MASTER_FACTOR_MIN, MAX_WINDOWS_PER_WORKSPACE, ROTATE_FOCUS_FOLLOWS_WINDOW, THEME, Theme};
+use crate::helper;
+use crate::layout::{bsp, floating, tiling, LayoutType};
+use crate::layout::bsp::BspTree;
 use crate::screen::Screen;
 use crate::windows::{Window, Windows};
 use crate::x::{XConn, XWindowID};
 
+// Workspace state read back from the state file, see Workspace::serialize
+#[derive(PartialEq, Debug)]
+pub struct SavedWorkspace {
+    pub idx: usize,
+    pub layout: LayoutType,
+    pub master_factor: f32,
+    pub master_count: usize,
+}
+
 pub struct Workspace {
+    // Name published to EWMH pagers, moves with the workspace when reordered
+    pub name: String,
+
     // Internal window id tracking
     pub windows: Windows,
 
+    // Window ids bottom to top, kept within layers of tiled, then floating, then above, then fullscreen
+    stack: Vec<XWindowID>,
+
     // Track if Workspace active (on-screen)
     pub active:  bool,
 
+    // Origin of the monitor last shown on, None until first shown
+    origin: Option<(i32, i32)>,
+
+    // Current layout, fraction of screen width given to the master area when tiling,
+    // and number of windows sharing the master area
+    pub layout: LayoutType,
+    pub master_factor: f32,
+    pub master_count: usize,
+
+    // Tiled windows split up for the bsp layout, kept when switching away so splits are as left
+    pub bsp: Option<BspTree>,
+
     // Layout functions
     // You're probably asking yourself, why are we doing it like this? Instead of say,
     // holding onto an object that implements a Layout trait? Well doing it that way
@@ -32,20 +62,30 @@ pub struct Workspace {
     // If you're reading this and you have more ideas please do let me know, I'm open to them :p
     _activate:             fn(&mut Workspace, &XConn, &Screen),
     _deactivate:           fn(&mut Workspace, &XConn),
-    _window_add:           fn(&mut Workspace, &XConn, &Screen, Window),
+    _arrange:              fn(&mut Workspace, &XConn, &Screen),
+    _window_add:           fn(&mut Workspace, &XConn, &Screen, Window, bool),
     _window_del:           fn(&mut Workspace, &XConn, &Screen, usize, XWindowID) -> Window,
-    _window_focus:         fn(&mut Workspace, &XConn, &Screen, XWindowID),
+    _window_focus:         fn(&mut Workspace, &XConn, &Screen, usize),
     _window_focus_cycle:   fn(&mut Workspace, &XConn, &Screen),
 }
 
 impl Default for Workspace {
     fn default() -> Self {
         Self {
+            name: String::new(),
             windows: Windows::default(),
+            stack: Vec::new(),
             active:  false,
+            origin: None,
+
+            layout: LayoutType::Floating,
+            master_factor: MASTER_FACTOR,
+            master_count: MASTER_COUNT,
+            bsp: None,
 
             _activate: floating::activate,
             _deactivate: floating::deactivate,
+            _arrange: floating::arrange,
             _window_add: floating::window_add,
             _window_del: floating::window_del,
             _window_focus: floating::window_focus,
@@ -55,24 +95,171 @@ impl Default for Workspace {
 }
 
 impl Workspace {
+    pub fn serialize(&self, idx: usize) -> String {
+        // Line of: "workspace", index, layout, master factor (in percent), master count
+        return format!("workspace {} {} {} {}", idx, self.layout as u32, (self.master_factor * 100.0).round() as u32, self.master_count);
+    }
+
+    pub fn deserialize(line: &str) -> Option<SavedWorkspace> {
+        // Every value must parse, a malformed line is skipped entirely
+        let mut values = line.split_whitespace();
+        if values.next() != Some("workspace") {
+            return None;
+        }
+        let values: Vec<u32> = values.map(|value| value.parse().ok()).collect::<Option<Vec<u32>>>()?;
+        if values.len() != 4 {
+            return None;
+        }
+        return Some(SavedWorkspace {
+            idx: values[0] as usize,
+            layout: LayoutType::from_u32(values[1])?,
+            master_factor: values[2] as f32 / 100.0,
+            master_count: (values[3] as usize).max(1),
+        });
+    }
+
     pub fn set_layout(&mut self, conn: &XConn, screen: &Screen, t: LayoutType) {
+        self.layout = t;
         match t {
             LayoutType::Floating => {
                 debug!("Switching to layout: floating");
+
+                // Put back any border tiling took away from a lone window
+                if AUTO_FULLSCREEN_SINGLE {
+                    for window in self.windows.iter().filter(|window| !window.fullscreen) {
+                        conn.set_border_width(&THEME, window.xwindow.id, window.border_width(&THEME));
+                    }
+                }
+
                 self._activate = floating::activate;
                 self._deactivate = floating::deactivate;
+                self._arrange = floating::arrange;
                 self._window_add = floating::window_add;
                 self._window_del = floating::window_del;
                 self._window_focus = floating::window_focus;
                 self._window_focus_cycle = floating::window_focus_cycle;
             },
+
+            LayoutType::Tiling => {
+                debug!("Switching to layout: tiling");
+                self._activate = tiling::activate;
+                self._deactivate = tiling::deactivate;
+                self._arrange = tiling::arrange;
+                self._window_add = tiling::window_add;
+                self._window_del = tiling::window_del;
+                self._window_focus = tiling::window_focus;
+                self._window_focus_cycle = tiling::window_focus_cycle;
+            },
+
+            LayoutType::Bsp => {
+                debug!("Switching to layout: bsp");
+                self._activate = bsp::activate;
+                self._deactivate = bsp::deactivate;
+                self._arrange = bsp::arrange;
+                self._window_add = bsp::window_add;
+                self._window_del = bsp::window_del;
+                self._window_focus = bsp::window_focus;
+                self._window_focus_cycle = bsp::window_focus_cycle;
+            },
+        }
+
+        // If on-screen, re-arrange using new layout
+        if self.active {
+            self.arrange(conn, screen);
+        }
+    }
+
+    pub fn set_master_factor(&mut self, conn: &XConn, screen: &Screen, factor: f32) {
+        // Keep within configured bounds
+        self.master_factor = factor.max(MASTER_FACTOR_MIN).min(MASTER_FACTOR_MAX);
+        debug!("Setting master factor: {}", self.master_factor);
+
+        // If on-screen, re-arrange using new factor
+        if self.active {
+            self.arrange(conn, screen);
+        }
+    }
+
+    pub fn set_master_count(&mut self, conn: &XConn, screen: &Screen, count: usize) {
+        // At least one master, at most every tiled window
+        let len = self.windows.iter().filter(|window| window.is_tiled()).count();
+        self.master_count = count.min(len).max(1);
+        debug!("Setting master count: {}", self.master_count);
+
+        // If on-screen, re-arrange using new count
+        if self.active {
+            self.arrange(conn, screen);
+        }
+    }
+
+    pub fn balance(&mut self, conn: &XConn, screen: &Screen) {
+        // Bsp splits go back to even
+        if self.layout == LayoutType::Bsp {
+            if let Some(tree) = self.bsp.as_mut() { tree.balance(); }
+            if self.active { self.arrange(conn, screen); }
+            return;
+        }
+
+        // Only the tiling layouts have anything to balance
+        if self.layout != LayoutType::Tiling {
+            return;
+        }
+
+        // Single master taking 1/n of the width gives every tiled window equal area. That's only
+        // possible past the factor's usual bounds with many windows, so they don't apply here
+        self.master_count = 1;
+        let len = self.windows.iter().filter(|window| window.is_tiled()).count();
+        if len > 1 {
+            self.master_factor = 1.0 / len as f32;
+            debug!("Balancing master factor: {}", self.master_factor);
+        }
+        if self.active {
+            self.arrange(conn, screen);
+        }
+    }
+
+    pub fn rotate_split_focused(&mut self, conn: &XConn, screen: &Screen) {
+        // Flip the bsp split holding the focused window between side by side and stacked
+        let window_id = match self.windows.focused() { Some(window) => window.xwindow.id, None => return };
+        if self.layout == LayoutType::Bsp && self.bsp.as_mut().map_or(false, |tree| tree.rotate(window_id)) && self.active {
+            self.arrange(conn, screen);
+        }
+    }
+
+    pub fn adjust_split_focused(&mut self, conn: &XConn, screen: &Screen, grow: bool) {
+        // Give the focused window more (or less) of the bsp split holding it
+        let window_id = match self.windows.focused() { Some(window) => window.xwindow.id, None => return };
+        let delta = if grow { BSP_RATIO_STEP } else { -BSP_RATIO_STEP };
+        if self.layout == LayoutType::Bsp && self.bsp.as_mut().map_or(false, |tree| tree.adjust_ratio(window_id, delta)) && self.active {
+            self.arrange(conn, screen);
         }
     }
 
     pub fn activate(&mut self, conn: &XConn, screen: &Screen) {
         debug!("Activating workspace");
+        self.move_to_monitor(conn, screen);
         (self._activate)(self, conn, screen);
         self.active = true;
+        self.restack(conn);
+    }
+
+    fn move_to_monitor(&mut self, conn: &XConn, screen: &Screen) {
+        // Last shown on another monitor, windows the layout doesn't place come along. Sticky windows
+        // are already where they belong, having come along from the workspace shown here
+        let origin = (screen.xwindow.x, screen.xwindow.y);
+        let (dx, dy) = match self.origin.replace(origin) {
+            Some((x, y)) if (x, y) != origin => (origin.0 - x, origin.1 - y),
+            _ => return,
+        };
+        debug!("Moving workspace to monitor at: {} {}", origin.0, origin.1);
+        let free = self.layout == LayoutType::Floating;
+        for window in self.windows.iter_mut().filter(|window| (free || !window.is_tiled()) && !window.sticky) {
+            window.move_to_monitor(conn, screen, dx, dy);
+        }
+    }
+
+    pub fn origin(&self) -> Option<(i32, i32)> {
+        return self.origin;
     }
 
     pub fn deactivate(&mut self, conn: &XConn) {
@@ -81,23 +268,37 @@ impl Workspace {
         self.active = false;
     }
 
-    pub fn window_add(&mut self, conn: &XConn, screen: &Screen, window: Window) {
+    pub fn arrange(&mut self, conn: &XConn, screen: &Screen) {
+        debug!("Arranging workspace");
+        (self._arrange)(self, conn, screen);
+    }
+
+    pub fn window_add(&mut self, conn: &XConn, screen: &Screen, window: Window, focus: bool) {
         debug!("Adding window to workspace: {}", window.xwindow.id);
-       (self._window_add)(self, conn, screen, window);
+        let window_id = window.xwindow.id;
+        (self._window_add)(self, conn, screen, window, focus);
+
+        // New windows go ontop (of their layer)
+        self.raise(conn, window_id);
     }
 
     pub fn window_del(&mut self, conn: &XConn, screen: &Screen, idx: usize, window_id: XWindowID) -> Window {
         debug!("Deleting window at index {} from workspace: {}", idx, window_id);
-        return (self._window_del)(self, conn, screen, idx, window_id);
+        let window = (self._window_del)(self, conn, screen, idx, window_id);
+        self.stack.retain(|id| *id != window_id);
+
+        // Focus may have moved to another window, bring it up
+        self.raise_focused(conn);
+        return window;
     }
 
     pub fn window_del_focused(&mut self, conn: &XConn, screen: &Screen) -> Option<Window> {
-        if let Some(focused) = self.windows.focused() {
+        if let Some(idx) = self.windows.focused_idx() {
             // Take ownership
-            let focused = focused.to_owned();
+            let focused = self.windows.get(idx).unwrap().to_owned();
 
             // Remove from current workspace
-            self.window_del(conn, screen, 0, focused.xwindow.id);
+            self.window_del(conn, screen, idx, focused.xwindow.id);
 
             // Return id of previously focused window
             return Some(focused);
@@ -106,12 +307,323 @@ impl Workspace {
     }
 
     pub fn window_focus(&mut self, conn: &XConn, screen: &Screen, window_id: XWindowID) {
-        debug!("Focusing window in workspace: {}", window_id);
-        (self._window_focus)(self, conn, screen, window_id);
+        // Focus window (if there!)
+        if let Some(idx) = self.windows.index_of(window_id) {
+            self.window_focus_idx(conn, screen, idx);
+        }
+    }
+
+    pub fn window_focus_idx(&mut self, conn: &XConn, screen: &Screen, idx: usize) {
+        // Only if index within bounds
+        if idx >= self.windows.len() {
+            return;
+        }
+        debug!("Focusing window at index in workspace: {}", idx);
+        (self._window_focus)(self, conn, screen, idx);
+        self.raise_focused(conn);
     }
 
     pub fn window_focus_cycle(&mut self, conn: &XConn, screen: &Screen) {
         debug!("Cycling focused window");
         (self._window_focus_cycle)(self, conn, screen);
+        self.raise_focused(conn);
+    }
+
+    pub fn raise(&mut self, conn: &XConn, window_id: XWindowID) {
+        // Move to the top of the stack, X follows
+        self.stack.retain(|id| *id != window_id);
+        self.stack.push(window_id);
+        self.restack_window(conn, window_id);
+    }
+
+    pub fn lower(&mut self, conn: &XConn, window_id: XWindowID) {
+        // Move to the bottom of the stack, X follows
+        self.stack.retain(|id| *id != window_id);
+        self.stack.insert(0, window_id);
+        self.restack_window(conn, window_id);
+    }
+
+    pub fn restack_sibling(&mut self, conn: &XConn, window_id: XWindowID, sibling_id: XWindowID, above: bool) {
+        // Move just above / below the sibling in the stack, X follows. A sibling that isn't ours
+        // is as good as none, so to the top / bottom
+        self.stack.retain(|id| *id != window_id);
+        match self.stack.iter().position(|id| *id == sibling_id) {
+            Some(pos) => self.stack.insert(if above { pos + 1 } else { pos }, window_id),
+            None if above => self.stack.push(window_id),
+            None => self.stack.insert(0, window_id),
+        }
+        self.restack_window(conn, window_id);
+    }
+
+    pub fn raise_focused(&mut self, conn: &XConn) {
+        if let Some(window_id) = self.windows.focused().map(|window| window.xwindow.id) {
+            self.raise(conn, window_id);
+        }
+    }
+
+    pub fn lower_focused(&mut self, conn: &XConn) {
+        if let Some(window_id) = self.windows.focused().map(|window| window.xwindow.id) {
+            self.lower(conn, window_id);
+        }
+    }
+
+    pub fn restack(&mut self, conn: &XConn) {
+        // Tell X the whole stack, top down so each window goes just below the one above it
+        for window_id in self.stack_order().iter().rev() {
+            self.restack_window(conn, *window_id);
+        }
+    }
+
+    fn restack_window(&mut self, conn: &XConn, window_id: XWindowID) {
+        // Only on-screen windows need stacking in X, it's redone on activation
+        if !self.active {
+            return;
+        }
+
+        // Place directly below the window above it, or ontop of everything if top
+        let order = self.stack_order();
+        let values = match order.iter().position(|id| *id == window_id).and_then(|pos| order.get(pos + 1)) {
+            Some(sibling_id) => helper::values_configure_stack_below(*sibling_id).to_vec(),
+            None => helper::values_configure_stack_above().to_vec(),
+        };
+
+        // Disable event tracking while restacking, so the window moving under the pointer doesn't steal focus
+        conn.change_window_attributes(window_id, &helper::values_attributes_no_events());
+        conn.configure_window(window_id, &values);
+        conn.change_window_attributes(window_id, &helper::values_attributes_child_events());
+    }
+
+    fn stack_order(&mut self) -> Vec<XWindowID> {
+        // Drop windows no longer here, and put any added behind our back (e.g. adopted) ontop
+        let windows = &self.windows;
+        self.stack.retain(|id| windows.contains(*id).is_some());
+        for window in self.windows.iter() {
+            if !self.stack.contains(&window.xwindow.id) {
+                self.stack.push(window.xwindow.id);
+            }
+        }
+
+        // Stable sort into layers, keeping stack order within each
+        let mut order = self.stack.clone();
+        order.sort_by_key(|id| match self.windows.contains(*id).and_then(|idx| self.windows.get(idx)) {
+            Some(window) if window.fullscreen => 3,
+            Some(window) if window.above => 2,
+            Some(window) if window.floating => 1,
+            _ => 0,
+        });
+        return order;
+    }
+
+    pub fn set_fullscreen(&mut self, conn: &XConn, screen: &Screen, theme: &Theme, idx: usize, fullscreen: bool) {
+        if let Some(window) = self.windows.get_mut(idx) {
+            debug!("Setting fullscreen {} for window: {}", fullscreen, window.xwindow.id);
+            window.set_fullscreen(conn, screen, theme, fullscreen);
+
+            // If on-screen, re-arrange as the window left / joined the layout, and changed layer
+            if self.active {
+                self.arrange(conn, screen);
+                self.restack(conn);
+            }
+        }
+    }
+
+    pub fn set_above(&mut self, conn: &XConn, idx: usize, above: bool) {
+        if let Some(window) = self.windows.get_mut(idx) {
+            debug!("Setting above {} for window: {}", above, window.xwindow.id);
+            window.set_above(conn, above);
+
+            // Changed layer, so restack
+            self.restack(conn);
+        }
+    }
+
+    pub fn toggle_above_focused(&mut self, conn: &XConn) {
+        if let Some(idx) = self.windows.focused_idx() {
+            let above = self.windows.get(idx).unwrap().above;
+            self.set_above(conn, idx, !above);
+        }
+    }
+
+    pub fn set_fullscreen_monitors(&mut self, conn: &XConn, screen: &Screen, idx: usize, monitors: [usize; 4]) {
+        if let Some(window) = self.windows.get_mut(idx) {
+            debug!("Setting fullscreen monitors {:?} for window: {}", monitors, window.xwindow.id);
+            window.set_fullscreen_monitors(conn, screen, monitors);
+        }
+    }
+
+    pub fn set_borderless(&mut self, conn: &XConn, screen: &Screen, theme: &Theme, idx: usize, borderless: bool) {
+        if let Some(window) = self.windows.get_mut(idx) {
+            debug!("Setting borderless {} for window: {}", borderless, window.xwindow.id);
+            window.set_borderless(conn, theme, borderless);
+
+            // If on-screen, re-arrange as the border counts towards the window's space
+            if self.active {
+                self.arrange(conn, screen);
+            }
+        }
+    }
+
+    pub fn toggle_fullscreen_focused(&mut self, conn: &XConn, screen: &Screen, theme: &Theme) {
+        if let Some(idx) = self.windows.focused_idx() {
+            let fullscreen = self.windows.get(idx).unwrap().fullscreen;
+            self.set_fullscreen(conn, screen, theme, idx, !fullscreen);
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        // Limit only applies to tiled windows, so floating workspaces and sticky / fullscreen / floating windows are exempt
+        match MAX_WINDOWS_PER_WORKSPACE {
+            Some(max) if self.layout != LayoutType::Floating => return self.windows.iter().filter(|window| !window.sticky && window.is_tiled()).count() >= max,
+            _ => return false,
+        }
+    }
+
+    pub fn is_urgent(&self) -> bool {
+        return self.windows.iter().any(|window| window.urgent);
+    }
+
+    pub fn swap_windows(&mut self, conn: &XConn, screen: &Screen, a: usize, b: usize) {
+        // Only swap if both indices within bounds
+        if a >= self.windows.len() || b >= self.windows.len() {
+            return;
+        }
+        debug!("Swapping windows at indices: {} {}", a, b);
+
+        // Internally swap, focus stays with the window
+        self.windows.swap(a, b);
+
+        // Re-arrange to apply new ordering
+        self.arrange(conn, screen);
+    }
+
+    pub fn swap_master(&mut self, conn: &XConn, screen: &Screen) {
+        // Swap the focused window with the master (index 0)
+        if let Some(idx) = self.windows.focused_idx() {
+            self.swap_windows(conn, screen, 0, idx);
+        }
+    }
+
+    pub fn promote_focused(&mut self, conn: &XConn, screen: &Screen) {
+        // Move the focused window into master, keeping the stack order. If
+        // already master, promote the next window along instead
+        let idx = match self.windows.focused_idx() {
+            Some(0) => 1,
+            Some(idx) => idx,
+            None => return,
+        };
+
+        // Nothing to promote on single window workspaces
+        if idx >= self.windows.len() {
+            return;
+        }
+        debug!("Promoting window at index: {}", idx);
+
+        // Internally promote, focus stays with the window
+        self.windows.promote(idx);
+
+        // Re-arrange to apply new ordering
+        self.arrange(conn, screen);
+    }
+
+    pub fn rotate_left(&mut self, conn: &XConn, screen: &Screen) {
+        self.rotate(conn, screen, false);
+    }
+
+    pub fn rotate_right(&mut self, conn: &XConn, screen: &Screen) {
+        self.rotate(conn, screen, true);
+    }
+
+    fn rotate(&mut self, conn: &XConn, screen: &Screen, right: bool) {
+        // Nothing to rotate on single window workspaces
+        let idx = match self.windows.focused_idx() {
+            Some(idx) if self.windows.len() > 1 => idx,
+            _ => return,
+        };
+        debug!("Rotating windows {}", if right { "right" } else { "left" });
+
+        // Internally rotate, focus follows the window
+        if right {
+            self.windows.rotate_right();
+        } else {
+            self.windows.rotate_left();
+        }
+
+        // Else move focus back to the same slot, now holding a different window
+        if !ROTATE_FOCUS_FOLLOWS_WINDOW {
+            self.windows.set_focused(idx);
+            if let Some(window) = self.windows.focused() {
+                conn.set_input_focus(window.xwindow.id);
+            }
+        }
+
+        // Re-arrange to apply new ordering
+        self.arrange(conn, screen);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialize_round_trip() {
+        let mut ws = Workspace::default();
+        ws.layout = LayoutType::Tiling;
+        ws.master_factor = 0.6;
+        ws.master_count = 2;
+
+        let line = ws.serialize(3);
+        assert_eq!(line, "workspace 3 1 60 2");
+        assert_eq!(Workspace::deserialize(&line), Some(SavedWorkspace { idx: 3, layout: LayoutType::Tiling, master_factor: 0.6, master_count: 2 }));
+    }
+
+    #[test]
+    fn deserialize_keeps_a_master() {
+        assert_eq!(Workspace::deserialize("workspace 0 2 50 0").map(|saved| saved.master_count), Some(1));
+    }
+
+    #[test]
+    fn deserialize_rejects_malformed() {
+        assert_eq!(Workspace::deserialize("workspace 0 7 50 1"), None);
+        assert_eq!(Workspace::deserialize("workspace 0 1 50"), None);
+        assert_eq!(Workspace::deserialize("workspace 0 1 -50 1"), None);
+        assert_eq!(Workspace::deserialize("window 0 1 50 1"), None);
+        assert_eq!(Workspace::deserialize(""), None);
+    }
+
+    #[test]
+    fn stack_tracks_windows() {
+        let mut ws = Workspace::default();
+        for id in 1..4 {
+            ws.windows.add(Window::from(id));
+        }
+
+        // Added behind our back go ontop, gone ones are dropped
+        ws.stack = vec![2, 5];
+        assert_eq!(ws.stack_order(), vec![2, 3, 1]);
+        assert_eq!(ws.stack, vec![2, 3, 1]);
+
+        // Floating above tiled, keeping stack order within each
+        ws.windows.get_mut(1).unwrap().floating = true;
+        assert_eq!(ws.stack_order(), vec![3, 1, 2]);
+        ws.windows.get_mut(0).unwrap().floating = true;
+        assert_eq!(ws.stack_order(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn above_layer() {
+        let mut ws = Workspace::default();
+        for id in 1..5 {
+            let mut window = Window::from(id);
+            window.floating = true;
+            ws.windows.add(window);
+        }
+        ws.stack = vec![1, 2, 3, 4];
+
+        // Always on top windows go over floating ones, with only fullscreen over them
+        ws.windows.get_mut(3).unwrap().above = true;
+        assert_eq!(ws.stack_order(), vec![2, 3, 4, 1]);
+        ws.windows.get_mut(1).unwrap().fullscreen = true;
+        assert_eq!(ws.stack_order(), vec![2, 4, 1, 3]);
+    }
+}
@@ -1,15 +1,165 @@
+use crate::config::Theme;
 use crate::helper;
 use crate::windows::Window;
 
+use std::collections::HashMap;
+use std::os::unix::io::{AsRawFd, RawFd};
 use xcb_util::{cursor, ewmh, icccm};
 use xcb_util::keysyms::KeySymbols;
 
+// Send a request without waiting on it. With the debug-sync feature it's checked immediately
+// instead, so any X error gets logged against the line calling the (#[track_caller]) wrapper
+#[cfg(not(feature="debug-sync"))]
+macro_rules! request {
+    ($unchecked:path, $checked:path, $($arg:expr),*) => { $unchecked($($arg),*); };
+}
+
+#[cfg(feature="debug-sync")]
+macro_rules! request {
+    ($unchecked:path, $checked:path, $($arg:expr),*) => {
+        if let Err(err) = $checked($($arg),*).request_check() {
+            let caller = std::panic::Location::caller();
+            warn!("X error {} from {} at {}:{}", err.error_code(), stringify!($unchecked), caller.file(), caller.line());
+        }
+    };
+}
+
+// Bindings to libxcb-cursor, loading cursors from the user's Xcursor theme
+#[cfg(feature = "xcursor")]
+mod xcursor {
+    use std::os::raw::{c_char, c_int};
+    use xcb::ffi::base::xcb_connection_t;
+    use xcb::ffi::xproto::xcb_screen_t;
+
+    pub enum Context {}
+
+    #[link(name = "xcb-cursor")]
+    extern "C" {
+        pub fn xcb_cursor_context_new(conn: *mut xcb_connection_t, screen: *mut xcb_screen_t, ctx: *mut *mut Context) -> c_int;
+        pub fn xcb_cursor_load_cursor(ctx: *mut Context, name: *const c_char) -> u32;
+        pub fn xcb_cursor_context_free(ctx: *mut Context);
+    }
+}
+
+#[derive(Clone, Copy)]
 pub enum CursorIndex {
     LeftPtr,
+    Move,
+    ResizeTop,
+    ResizeTopRight,
+    ResizeRight,
+    ResizeBottomRight,
+    ResizeBottom,
+    ResizeBottomLeft,
+    ResizeLeft,
+    ResizeTopLeft,
 }
 
+impl CursorIndex {
+    pub fn resize(edge_x: i32, edge_y: i32) -> Self {
+        // Get resize cursor for the edge(s) being resized from
+        match (edge_x.signum(), edge_y.signum()) {
+            ( 0, -1) => return CursorIndex::ResizeTop,
+            ( 1, -1) => return CursorIndex::ResizeTopRight,
+            ( 1,  0) => return CursorIndex::ResizeRight,
+            ( 0,  1) => return CursorIndex::ResizeBottom,
+            (-1,  1) => return CursorIndex::ResizeBottomLeft,
+            (-1,  0) => return CursorIndex::ResizeLeft,
+            (-1, -1) => return CursorIndex::ResizeTopLeft,
+            _        => return CursorIndex::ResizeBottomRight,
+        }
+    }
+}
+
+// Number of cursors in CursorIndex
+const CURSOR_COUNT: usize = 10;
+
 pub type XWindowID = xcb::Window;
 
+// _NET_WM_DESKTOP value for windows on all desktops
+const ALL_DESKTOPS: u32 = 0xFFFFFFFF;
+
+// WM_HINTS flags field urgency bit
+const WM_HINTS_URGENCY: u32 = 1 << 8;
+
+// _MOTIF_WM_HINTS flags field bit marking the decorations field as set
+const MOTIF_HINTS_DECORATIONS: u32 = 1 << 1;
+
+// Whether _MOTIF_WM_HINTS ask for decorations, if they say either way
+fn motif_decorations(hints: &[u32]) -> Option<bool> {
+    // Fields are (flags, functions, decorations, input mode, status), decorations only count if flagged
+    match hints {
+        [flags, _, decorations, ..] if flags & MOTIF_HINTS_DECORATIONS != 0 => return Some(*decorations != 0),
+        _ => return None,
+    }
+}
+
+// WM_NORMAL_HINTS flags field bits for min size, resize increments, aspect and base size
+const SIZE_HINTS_MIN_SIZE: u32 = 1 << 4;
+const SIZE_HINTS_RESIZE_INC: u32 = 1 << 6;
+const SIZE_HINTS_ASPECT: u32 = 1 << 7;
+const SIZE_HINTS_BASE_SIZE: u32 = 1 << 8;
+
+// Whether a property reply holds 32-bit values of the type asked for
+fn is_property32(format: u8, type_: xcb::Atom, expected: xcb::Atom) -> bool {
+    return format == 32 && type_ == expected;
+}
+
+// WM_NORMAL_HINTS fields, of which we use only some
+pub struct SizeHints {
+    values: Vec<u32>,
+}
+
+impl SizeHints {
+    fn flags(&self) -> u32 {
+        return self.values.get(0).copied().unwrap_or(0);
+    }
+
+    fn pair(&self, idx: usize) -> Option<(u32, u32)> {
+        // Ignore unset / nonsense zero values
+        match (self.values.get(idx), self.values.get(idx + 1)) {
+            (Some(a), Some(b)) if *a > 0 && *b > 0 => return Some((*a, *b)),
+            _ => return None,
+        }
+    }
+
+    pub fn aspect(&self) -> (Option<(u32, u32)>, Option<(u32, u32)>) {
+        // Min and max aspect ratio as (numerator, denominator)
+        if self.flags() & SIZE_HINTS_ASPECT == 0 {
+            return (None, None);
+        }
+        return (self.pair(11), self.pair(13));
+    }
+
+    pub fn resize_inc(&self) -> Option<(u32, u32)> {
+        // Width and height increments, e.g. terminal character cells
+        if self.flags() & SIZE_HINTS_RESIZE_INC == 0 {
+            return None;
+        }
+        return self.pair(9);
+    }
+
+    pub fn base_size(&self) -> (u32, u32) {
+        // Base size that increments count from, which ICCCM says falls back to min size
+        if self.flags() & SIZE_HINTS_BASE_SIZE != 0 {
+            return (self.values.get(15).copied().unwrap_or(0), self.values.get(16).copied().unwrap_or(0));
+        } else if self.flags() & SIZE_HINTS_MIN_SIZE != 0 {
+            return (self.values.get(5).copied().unwrap_or(0), self.values.get(6).copied().unwrap_or(0));
+        }
+        return (0, 0);
+    }
+}
+
+pub struct WmHints {
+    pub flags: u32,
+}
+
+impl WmHints {
+    pub fn is_urgent(&self) -> bool {
+        return self.flags & WM_HINTS_URGENCY != 0;
+    }
+}
+
 #[derive(Clone)]
 pub struct XWindow {
     pub id: XWindowID,
@@ -50,32 +200,173 @@ impl XWindow {
             self.height = height;
         }
     }
+
+    pub fn center(&self) -> (i32, i32) {
+        // Relative to the window itself, as warping the pointer to it takes
+        return (self.width / 2, self.height / 2);
+    }
+}
+
+// Non-EWMH atoms we use, interned up front so none incur a round-trip while handling events
+const CACHED_ATOMS: &[&str] = &[
+    "UTF8_STRING",
+    "WM_DELETE_WINDOW",
+    "_MOTIF_WM_HINTS",
+    "_AFWM_LAYOUT_NAME",
+    "_NET_WM_WINDOW_OPACITY",
+];
+
+#[derive(Default)]
+pub struct AtomCache {
+    atoms: HashMap<&'static str, xcb::Atom>,
+}
+
+impl AtomCache {
+    fn intern_all(&mut self, conn: &xcb::Connection, names: &[&'static str]) {
+        // Send all requests before waiting on any reply, so it's a single round-trip
+        let cookies: Vec<_> = self.uncached(names).into_iter()
+            .map(|name| (name, xcb::intern_atom(conn, false, name)))
+            .collect();
+
+        for (name, cookie) in cookies {
+            match cookie.get_reply() {
+                Ok(reply) => { self.atoms.insert(name, reply.atom()); },
+                Err(_) => warn!("Failed interning atom: {}", name),
+            }
+        }
+    }
+
+    fn intern(&mut self, conn: &xcb::Connection, name: &'static str) -> xcb::Atom {
+        // Cached, no need to ask X
+        if let Some(atom) = self.cached(name) {
+            return atom;
+        }
+        debug!("Interning uncached atom: {}", name);
+
+        // Intern and cache
+        let atom = xcb::intern_atom(conn, false, name).get_reply().expect("Interning atom").atom();
+        self.atoms.insert(name, atom);
+        return atom;
+    }
+
+    fn uncached(&self, names: &[&'static str]) -> Vec<&'static str> {
+        // Names not interned yet, once each
+        let mut uncached = Vec::new();
+        for name in names {
+            if !self.atoms.contains_key(name) && !uncached.contains(name) {
+                uncached.push(*name);
+            }
+        }
+        return uncached;
+    }
+
+    fn cached(&self, name: &str) -> Option<xcb::Atom> {
+        return self.atoms.get(name).copied();
+    }
 }
 
 pub struct InternedAtoms {
-    pub SUPPORTED:              xcb::Atom,
-    pub WM_DELETE_WINDOW:       xcb::Atom,
-    pub WM_PROTOCOLS:           xcb::Atom,
-    pub WM_WINDOW_TYPE_NORMAL:  xcb::Atom,
-    pub WM_WINDOW_TYPE_DIALOG:  xcb::Atom,
-    pub WM_WINDOW_TYPE_TOOLBAR: xcb::Atom,
-    pub WM_WINDOW_TYPE_UTILITY: xcb::Atom,
-    pub WM_WINDOW_TYPE_SPLASH:  xcb::Atom,
+    pub CLOSE_WINDOW:               xcb::Atom,
+    pub CURRENT_DESKTOP:            xcb::Atom,
+    pub DESKTOP_NAMES:              xcb::Atom,
+    pub LAYOUT_NAME:                xcb::Atom,
+    pub MOTIF_WM_HINTS:             xcb::Atom,
+    pub MOVERESIZE_WINDOW:          xcb::Atom,
+    pub NUMBER_OF_DESKTOPS:         xcb::Atom,
+    pub RESTACK_WINDOW:             xcb::Atom,
+    pub SUPPORTED:                  xcb::Atom,
+    pub SUPPORTING_WM_CHECK:        xcb::Atom,
+    pub UTF8_STRING:                xcb::Atom,
+    pub WM_DESKTOP:                 xcb::Atom,
+    pub WM_DELETE_WINDOW:           xcb::Atom,
+    pub WM_FULLSCREEN_MONITORS:     xcb::Atom,
+    pub WM_NAME:                    xcb::Atom,
+    pub WM_PROTOCOLS:               xcb::Atom,
+    pub WM_STATE:                   xcb::Atom,
+    pub WM_STATE_ABOVE:             xcb::Atom,
+    pub WM_STATE_DEMANDS_ATTENTION: xcb::Atom,
+    pub WM_STATE_FULLSCREEN:        xcb::Atom,
+    pub WM_STATE_STICKY:            xcb::Atom,
+    pub WM_STRUT:                   xcb::Atom,
+    pub WM_STRUT_PARTIAL:           xcb::Atom,
+    pub WM_USER_TIME:               xcb::Atom,
+    pub WM_WINDOW_TYPE:             xcb::Atom,
+    pub WM_WINDOW_TYPE_NORMAL:      xcb::Atom,
+    pub WM_WINDOW_TYPE_DIALOG:      xcb::Atom,
+    pub WM_WINDOW_TYPE_TOOLBAR:     xcb::Atom,
+    pub WM_WINDOW_TYPE_UTILITY:     xcb::Atom,
+    pub WM_WINDOW_TYPE_SPLASH:      xcb::Atom,
+    pub WM_WINDOW_OPACITY:          xcb::Atom,
 }
 
 impl InternedAtoms {
-    fn new(conn: &ewmh::Connection) -> Self {
+    fn new(conn: &ewmh::Connection, cache: &mut AtomCache) -> Self {
         Self {
-            SUPPORTED:              conn.SUPPORTED(),
-            WM_DELETE_WINDOW:       xcb::intern_atom(conn, false, "WM_DELETE_WINDOW").get_reply().expect("Interning WM_DELETE_WINDOW atom").atom(),
-            WM_PROTOCOLS:           conn.WM_PROTOCOLS(),
-            WM_WINDOW_TYPE_NORMAL:  conn.WM_WINDOW_TYPE_NORMAL(),
-            WM_WINDOW_TYPE_DIALOG:  conn.WM_WINDOW_TYPE_DIALOG(),
-            WM_WINDOW_TYPE_TOOLBAR: conn.WM_WINDOW_TYPE_TOOLBAR(),
-            WM_WINDOW_TYPE_UTILITY: conn.WM_WINDOW_TYPE_UTILITY(),
-            WM_WINDOW_TYPE_SPLASH:  conn.WM_WINDOW_TYPE_SPLASH(),
+            CLOSE_WINDOW:               conn.CLOSE_WINDOW(),
+            CURRENT_DESKTOP:            conn.CURRENT_DESKTOP(),
+            DESKTOP_NAMES:              conn.DESKTOP_NAMES(),
+            LAYOUT_NAME:                cache.intern(conn, "_AFWM_LAYOUT_NAME"),
+            MOTIF_WM_HINTS:             cache.intern(conn, "_MOTIF_WM_HINTS"),
+            MOVERESIZE_WINDOW:          conn.MOVERESIZE_WINDOW(),
+            NUMBER_OF_DESKTOPS:         conn.NUMBER_OF_DESKTOPS(),
+            RESTACK_WINDOW:             conn.RESTACK_WINDOW(),
+            SUPPORTED:                  conn.SUPPORTED(),
+            SUPPORTING_WM_CHECK:        conn.SUPPORTING_WM_CHECK(),
+            UTF8_STRING:                cache.intern(conn, "UTF8_STRING"),
+            WM_DESKTOP:                 conn.WM_DESKTOP(),
+            WM_DELETE_WINDOW:           cache.intern(conn, "WM_DELETE_WINDOW"),
+            WM_FULLSCREEN_MONITORS:     conn.WM_FULLSCREEN_MONITORS(),
+            WM_NAME:                    conn.WM_NAME(),
+            WM_PROTOCOLS:               conn.WM_PROTOCOLS(),
+            WM_STATE:                   conn.WM_STATE(),
+            WM_STATE_ABOVE:             conn.WM_STATE_ABOVE(),
+            WM_STATE_DEMANDS_ATTENTION: conn.WM_STATE_DEMANDS_ATTENTION(),
+            WM_STATE_FULLSCREEN:        conn.WM_STATE_FULLSCREEN(),
+            WM_STATE_STICKY:            conn.WM_STATE_STICKY(),
+            WM_STRUT:                   conn.WM_STRUT(),
+            WM_STRUT_PARTIAL:           conn.WM_STRUT_PARTIAL(),
+            WM_USER_TIME:               conn.WM_USER_TIME(),
+            WM_WINDOW_TYPE:             conn.WM_WINDOW_TYPE(),
+            WM_WINDOW_TYPE_NORMAL:      conn.WM_WINDOW_TYPE_NORMAL(),
+            WM_WINDOW_TYPE_DIALOG:      conn.WM_WINDOW_TYPE_DIALOG(),
+            WM_WINDOW_TYPE_TOOLBAR:     conn.WM_WINDOW_TYPE_TOOLBAR(),
+            WM_WINDOW_TYPE_UTILITY:     conn.WM_WINDOW_TYPE_UTILITY(),
+            WM_WINDOW_TYPE_SPLASH:      conn.WM_WINDOW_TYPE_SPLASH(),
+            WM_WINDOW_OPACITY:          cache.intern(conn, "_NET_WM_WINDOW_OPACITY"),
         }
     }
+
+    pub fn supported(&self) -> Vec<xcb::Atom> {
+        // EWMH atoms we implement, to be advertised in _NET_SUPPORTED.
+        // Keep this updated as new EWMH features are implemented!
+        return vec![
+            self.CLOSE_WINDOW,
+            self.CURRENT_DESKTOP,
+            self.DESKTOP_NAMES,
+            self.MOVERESIZE_WINDOW,
+            self.NUMBER_OF_DESKTOPS,
+            self.RESTACK_WINDOW,
+            self.SUPPORTED,
+            self.SUPPORTING_WM_CHECK,
+            self.WM_DESKTOP,
+            self.WM_FULLSCREEN_MONITORS,
+            self.WM_NAME,
+            self.WM_STATE,
+            self.WM_STATE_ABOVE,
+            self.WM_STATE_DEMANDS_ATTENTION,
+            self.WM_STATE_FULLSCREEN,
+            self.WM_STATE_STICKY,
+            self.WM_STRUT,
+            self.WM_STRUT_PARTIAL,
+            self.WM_USER_TIME,
+            self.WM_WINDOW_TYPE,
+            self.WM_WINDOW_TYPE_NORMAL,
+            self.WM_WINDOW_TYPE_DIALOG,
+            self.WM_WINDOW_TYPE_TOOLBAR,
+            self.WM_WINDOW_TYPE_UTILITY,
+            self.WM_WINDOW_TYPE_SPLASH,
+        ];
+    }
 }
 
 pub struct XConn<'a> {
@@ -83,32 +374,115 @@ pub struct XConn<'a> {
     pub conn: &'a ewmh::Connection,
 
     // Stored loaded cursor ids
-    cursors: [u32; 1],
+    cursors: [u32; CURSOR_COUNT],
 
     // KeySymbol lookup object
     key_syms: KeySymbols<'a>,
 
     // Interned atoms
     pub atoms: InternedAtoms,
+
+    // Shape extension available on the server
+    has_shape: bool,
+
+    // RandR extension, new enough (1.2+) to list CRTCs, available on the server
+    has_randr: bool,
 }
 
 impl<'a> XConn<'a> {
     pub fn new(conn: &'a ewmh::Connection) -> Self {
-        // Create new atoms object
-        let atoms = InternedAtoms::new(conn);
+        // Intern our own atoms in one round-trip, then create new atoms object from them
+        let mut atom_cache = AtomCache::default();
+        atom_cache.intern_all(conn, CACHED_ATOMS);
+        let atoms = InternedAtoms::new(conn, &mut atom_cache);
 
         // Create new Self
         let new = Self {
             conn:     conn,
-            cursors:  [0; 1],
+            cursors:  [0; CURSOR_COUNT],
             key_syms: KeySymbols::new(conn),
             atoms:    atoms,
+            has_shape: Self::query_shape(conn),
+            has_randr: Self::query_randr(conn),
         };
 
         // Return the new Self
         return new;
     }
 
+    #[cfg(feature = "shape")]
+    fn query_shape(conn: &ewmh::Connection) -> bool {
+        let present = conn.get_extension_data(xcb::shape::id()).map_or(false, |data| data.present());
+        debug!("Shape extension present: {}", present);
+        return present;
+    }
+
+    #[cfg(not(feature = "shape"))]
+    fn query_shape(_conn: &ewmh::Connection) -> bool {
+        return false;
+    }
+
+    #[cfg(feature = "randr")]
+    fn query_randr(conn: &ewmh::Connection) -> bool {
+        if !conn.get_extension_data(xcb::randr::id()).map_or(false, |data| data.present()) {
+            debug!("RandR extension present: false");
+            return false;
+        }
+
+        // CRTC queries arrived in 1.2
+        let present = match xcb::randr::query_version(conn, 1, 2).get_reply() {
+            Ok(reply) => reply.major_version() > 1 || reply.minor_version() >= 2,
+            Err(_) => false,
+        };
+        debug!("RandR extension present: {}", present);
+        return present;
+    }
+
+    #[cfg(not(feature = "randr"))]
+    fn query_randr(_conn: &ewmh::Connection) -> bool {
+        return false;
+    }
+
+    #[cfg(feature = "xcursor")]
+    pub fn create_cursors(&mut self, screen_idx: i32, cursors: &[(CursorIndex, &str, u16)]) {
+        debug!("Creating cursors from Xcursor theme");
+
+        // Get a cursor context for the screen, which reads the theme from resources / environment
+        let screen = self.conn.get_setup().roots().nth(screen_idx as usize).expect("Getting screen for cursor context");
+        let mut ctx = std::ptr::null_mut();
+        if unsafe { xcursor::xcb_cursor_context_new((**self.conn).get_raw_conn(), screen.ptr, &mut ctx) } < 0 {
+            warn!("Creating cursor context failed, falling back to core cursors");
+            for (cursor, _, glyph) in cursors {
+                self.create_core_cursor(*cursor, *glyph);
+            }
+            return;
+        }
+
+        // Load each from the theme, falling back to the core cursor if the theme lacks it
+        for (cursor, name, glyph) in cursors {
+            let cursor_id = match std::ffi::CString::new(*name) {
+                Ok(name) => unsafe { xcursor::xcb_cursor_load_cursor(ctx, name.as_ptr()) },
+                Err(_) => xcb::NONE,
+            };
+            if cursor_id == xcb::NONE {
+                debug!("Cursor missing from theme, using core cursor: {}", name);
+                self.create_core_cursor(*cursor, *glyph);
+            } else {
+                self.cursors[*cursor as usize] = cursor_id;
+            }
+        }
+
+        unsafe { xcursor::xcb_cursor_context_free(ctx) };
+    }
+
+    #[cfg(not(feature = "xcursor"))]
+    pub fn create_cursors(&mut self, _screen_idx: i32, cursors: &[(CursorIndex, &str, u16)]) {
+        // No theme support, use the core cursor font
+        for (cursor, _, glyph) in cursors {
+            self.create_core_cursor(*cursor, *glyph);
+        }
+    }
+
     pub fn create_core_cursor(&mut self, cursor: CursorIndex, cursor_glyph: u16) {
         // Try load cursor for supplied cursor glyp
         let cursor_id = cursor::create_font_cursor_checked(self.conn, cursor_glyph).expect("Creating font cursor");
@@ -130,6 +504,84 @@ impl<'a> XConn<'a> {
         ewmh::set_supported(self.conn, screen_idx, &atoms);
     }
 
+    pub fn set_number_of_desktops(&self, screen_idx: i32, number: usize) {
+        debug!("Setting number of desktops: {}", number);
+
+        // Set EWMH desktop count on root window
+        ewmh::set_number_of_desktops(self.conn, screen_idx, number as u32);
+    }
+
+    pub fn set_current_desktop(&self, screen_idx: i32, idx: usize) {
+        debug!("Setting current desktop: {}", idx);
+
+        // Set EWMH current desktop index on root window
+        ewmh::set_current_desktop(self.conn, screen_idx, idx as u32);
+    }
+
+    pub fn set_desktop_names(&self, screen_idx: i32, names: &[&str]) {
+        debug!("Setting desktop names: {:?}", names);
+
+        // Set EWMH UTF-8 desktop names list on root window, in desktop order
+        ewmh::set_desktop_names(self.conn, screen_idx, names.iter().copied());
+    }
+
+    pub fn set_wm_desktop(&self, window_id: XWindowID, idx: usize) {
+        debug!("Setting desktop for window: {} {}", window_id, idx);
+
+        // Set EWMH desktop index the window belongs to
+        ewmh::set_wm_desktop(self.conn, window_id, idx as u32);
+    }
+
+    pub fn set_wm_desktop_all(&self, window_id: XWindowID) {
+        debug!("Setting all desktops for window: {}", window_id);
+
+        // Set EWMH desktop index meaning the window is on all desktops
+        ewmh::set_wm_desktop(self.conn, window_id, ALL_DESKTOPS);
+    }
+
+    pub fn create_check_window(&self, root_id: XWindowID) -> XWindowID {
+        debug!("Creating supporting wm check window");
+
+        // Generate new window id
+        let window_id = self.conn.generate_id();
+
+        // Create a 1x1 input-only window offscreen, override-redirect so we don't try to manage it
+        xcb::create_window(
+            self.conn,
+            xcb::COPY_FROM_PARENT as u8,              // depth
+            window_id,                                // window id
+            root_id,                                  // parent window
+            -1, -1,                                   // x, y
+            1, 1,                                     // width, height
+            0,                                        // border width
+            xcb::WINDOW_CLASS_INPUT_ONLY as u16,      // window class
+            xcb::COPY_FROM_PARENT,                    // visual
+            &helper::values_attributes_override_redirect(),
+        );
+
+        // Return the new window id
+        return window_id;
+    }
+
+    pub fn set_supporting_wm_check(&self, window_id: XWindowID, check_id: XWindowID) {
+        debug!("Setting supporting wm check for window: {}", window_id);
+
+        // Set supporting wm check property on window to check window
+        ewmh::set_supporting_wm_check(self.conn, window_id, check_id);
+    }
+
+    pub fn set_wm_name(&self, window_id: XWindowID, name: &str) {
+        debug!("Setting wm name for window: {}", window_id);
+
+        // Set EWMH UTF-8 window name
+        ewmh::set_wm_name(self.conn, window_id, name);
+    }
+
+    pub fn set_root_name(&self, root_id: XWindowID, name: &str) {
+        // Set ICCCM window name, as read by xsetroot-style bars
+        icccm::set_wm_name(self.conn, root_id, name);
+    }
+
     pub fn get_setup(&self) -> xcb::Setup {
         debug!("Getting setup");
 
@@ -144,32 +596,73 @@ impl<'a> XConn<'a> {
         return xcb::query_tree(self.conn, window_id).get_reply().expect("Querying tree").children().to_owned();
     }
 
+    #[cfg_attr(feature="debug-sync", track_caller)]
     pub fn map_window(&self, window_id: XWindowID) {
         debug!("Mapping window: {}", window_id);
 
         // Map window. Don't bother checking, if it failed, it failed :shrug:
-        xcb::map_window(self.conn, window_id);
+        request!(xcb::map_window, xcb::map_window_checked, self.conn, window_id);
+    }
+
+    pub fn hide_window(&self, window_id: XWindowID) {
+        debug!("Hiding window: {}", window_id);
+
+        // Unmap with event tracking disabled, so we don't see the UnmapNotify and untrack the window.
+        // Any hidden window must go through here, it's the one place to hook unmap bookkeeping
+        self.change_window_attributes(window_id, &helper::values_attributes_no_events());
+        self.unmap_window(window_id);
+        self.change_window_attributes(window_id, &helper::values_attributes_child_events());
     }
 
+    #[cfg_attr(feature="debug-sync", track_caller)]
+    pub fn change_save_set(&self, window_id: XWindowID, mode: xcb::SetMode) {
+        debug!("Changing save set for window: {} {}", window_id, mode);
+
+        // Windows in our save set get remapped by X if we exit without cleaning up. Don't bother checking,
+        // destroyed windows are removed from it by X anyway
+        request!(xcb::change_save_set, xcb::change_save_set_checked, self.conn, mode as u8, window_id);
+    }
+
+    #[cfg_attr(feature="debug-sync", track_caller)]
     pub fn unmap_window(&self, window_id: XWindowID) {
         debug!("Unmapping window: {}", window_id);
 
         // Unmap window. Don't bother checking, if it failed, it failed :shrug:
-        xcb::unmap_window(self.conn, window_id);
+        request!(xcb::unmap_window, xcb::unmap_window_checked, self.conn, window_id);
     }
 
+    #[cfg_attr(feature="debug-sync", track_caller)]
     pub fn configure_window(&self, window_id: XWindowID, values: &[(u16, u32)]) {
         debug!("Configuring window: {}", window_id);
 
         // Configure window. Don't bother checking, if it failed, it failed :shrug:
-        xcb::configure_window(self.conn, window_id, values);
+        request!(xcb::configure_window, xcb::configure_window_checked, self.conn, window_id, values);
     }
 
+    #[cfg_attr(feature="debug-sync", track_caller)]
     pub fn change_window_attributes(&self, window_id: XWindowID, values: &[(u32, u32)]) {
         debug!("Changing window attributes: {}", window_id);
 
         // Change window attributes. Don't bother checking, if it failed, it failed :shrug:
-        xcb::change_window_attributes(self.conn, window_id, values);
+        request!(xcb::change_window_attributes, xcb::change_window_attributes_checked, self.conn, window_id, values);
+    }
+
+    #[cfg_attr(feature="debug-sync", track_caller)]
+    pub fn set_border_width(&self, theme: &Theme, window_id: XWindowID, width: u32) {
+        // Zero-border setups never set a border, so skip the request entirely
+        if theme.border_width == 0 {
+            return;
+        }
+        self.configure_window(window_id, &helper::values_configure_border_width(width));
+    }
+
+    #[cfg_attr(feature="debug-sync", track_caller)]
+    pub fn set_border_color(&self, theme: &Theme, window_id: XWindowID, color: u32) {
+        // No border to color in zero-border setups
+        if theme.border_width == 0 {
+            return;
+        }
+        self.change_window_attributes(window_id, &helper::values_attributes_border_color(color));
     }
 
     pub fn change_window_attributes_checked(&self, window_id: XWindowID, values: &[(u32, u32)]) {
@@ -179,11 +672,12 @@ impl<'a> XConn<'a> {
         xcb::change_window_attributes_checked(self.conn, window_id, values).request_check().expect("Changing window attributes");
     }
 
+    #[cfg_attr(feature="debug-sync", track_caller)]
     pub fn set_input_focus(&self, window_id: XWindowID) {
         debug!("Setting input focus window: {}", window_id);
 
         // Set input focus on window. Don't bother checking, if it failed, it failed :shrug:
-        xcb::set_input_focus(self.conn, xcb::INPUT_FOCUS_POINTER_ROOT as u8, window_id, xcb::CURRENT_TIME);
+        request!(xcb::set_input_focus, xcb::set_input_focus_checked, self.conn, xcb::INPUT_FOCUS_POINTER_ROOT as u8, window_id, xcb::CURRENT_TIME);
     }
 
     pub fn destroy_window(&self, window: &Window) {
@@ -194,20 +688,8 @@ impl<'a> XConn<'a> {
             // Window support ICCCM method of WM_DELETE_WINDOW
             debug!("Destroy window via ICCCM WM_DELETE_WINDOW");
 
-            // Create client message data
-            let msg_data = xcb::ClientMessageData::from_data32([self.atoms.WM_DELETE_WINDOW, xcb::CURRENT_TIME, 0, 0, 0]);
-
-            // Create event from message data
-            let event = xcb::ClientMessageEvent::new(32, window.xwindow.id, self.atoms.WM_PROTOCOLS, msg_data);
-
-            // Send the event!
-            xcb::send_event(
-                self.conn,                // connection
-                false,                    // propagate?
-                window.xwindow.id,        // destination window
-                xcb::EVENT_MASK_NO_EVENT, // event mask
-                &event,                   // event object
-            );
+            // Ask the client to close itself
+            self.send_client_message(window.xwindow.id, self.atoms.WM_PROTOCOLS, [self.atoms.WM_DELETE_WINDOW, xcb::CURRENT_TIME, 0, 0, 0]);
         } else {
             // Use plain-old X destroy window
             debug!("Destroy window via xcb_destroy_window");
@@ -215,52 +697,118 @@ impl<'a> XConn<'a> {
         }
     }
 
+    pub fn send_client_message(&self, window_id: XWindowID, type_: xcb::Atom, data: [u32; 5]) {
+        debug!("Sending client message to window: {}", window_id);
+
+        // Create event from message data
+        let event = xcb::ClientMessageEvent::new(32, window_id, type_, xcb::ClientMessageData::from_data32(data));
+
+        // Send the event!
+        xcb::send_event(
+            self.conn,                // connection
+            false,                    // propagate?
+            window_id,                // destination window
+            xcb::EVENT_MASK_NO_EVENT, // event mask
+            &event,                   // event object
+        );
+    }
+
     pub fn grab_key(&self, window_id: XWindowID, mask: xcb::ModMask, keysym: xcb::Keysym) {
         debug!("Grabbing key with mask:{} sym:{} for window: {}", mask, keysym, window_id);
 
-        // Get code iter for keysym
-        let code = self.key_syms.get_keycode(keysym).next();
+        // Resolve all keycodes for keysym using current keyboard mapping
+        let codes: Vec<xcb::Keycode> = self.key_syms.get_keycode(keysym).collect();
 
-        // If no code, log and move-on. Else, unwrap
-        if code.is_none() {
+        // If no codes, log and move-on
+        if codes.is_empty() {
             warn!("Keysym {} translated to zero-length keycode iter, not grabbing", keysym);
             return;
         }
-        let code = code.unwrap();
 
-        // Register key code to grab with X. We don't bother checking as only ever for root window
-        xcb::grab_key(
+        // Register each key code to grab with X, with every lock modifier combination. We don't bother checking as only ever for root window
+        for code in codes {
+            for lock_mask in helper::LOCK_MASK_COMBINATIONS.iter() {
+                xcb::grab_key(
+                    self.conn,
+                    false,                       // owner events (a.k.a don't pass on events to root window)
+                    window_id,                   // window id
+                    (mask|lock_mask) as u16,     // key mod mask
+                    code,                        // keycode
+                    xcb::GRAB_MODE_ASYNC as u8,  // pointer mode
+                    xcb::GRAB_MODE_ASYNC as u8   // keyboard mode
+                );
+            }
+        }
+    }
+
+    pub fn ungrab_all_keys(&self, window_id: XWindowID) {
+        debug!("Ungrabbing all keys for window: {}", window_id);
+
+        // Unregister all key grabs on window, any key with any modifiers. We don't bother checking as only ever for root window
+        xcb::ungrab_key(self.conn, xcb::GRAB_ANY as u8, window_id, xcb::MOD_MASK_ANY as u16);
+    }
+
+    pub fn refresh_keyboard_mapping(&self, event: &xcb::MappingNotifyEvent) {
+        debug!("Refreshing keyboard mapping");
+
+        // Update the keysym lookup object's keyboard mapping
+        self.key_syms.refresh_keyboard_mapping(event);
+    }
+
+    pub fn grab_keyboard(&self, window_id: XWindowID) -> bool {
+        debug!("Grabbing keyboard for window: {}", window_id);
+
+        // Grab the keyboard, check it went through okay as it may already be grabbed
+        match xcb::grab_keyboard(
             self.conn,
             false,                       // owner events (a.k.a don't pass on events to root window)
-            window_id,                   // window id
-            mask as u16,                 // key mod mask
-            code,                        // keycode
+            window_id,                   // grab window
+            xcb::CURRENT_TIME,           // time
             xcb::GRAB_MODE_ASYNC as u8,  // pointer mode
-            xcb::GRAB_MODE_ASYNC as u8   // keyboard mode
-        );
+            xcb::GRAB_MODE_ASYNC as u8,  // keyboard mode
+        ).get_reply() {
+            Ok(reply) => return reply.status() as u32 == xcb::GRAB_STATUS_SUCCESS,
+            Err(err) => {
+                warn!("Failed grabbing keyboard ({})", err);
+                return false;
+            },
+        }
+    }
+
+    pub fn ungrab_keyboard(&self) {
+        debug!("Ungrabbing keyboard");
+
+        // Unregister grabbing the keyboard. We don't bother checking as only ever for root window
+        xcb::ungrab_keyboard(self.conn, xcb::CURRENT_TIME);
     }
 
     pub fn grab_button(&self, window_id: XWindowID, mask: xcb::ButtonMask, button: xcb::ButtonIndex, modmask: xcb::ModMask, confine: bool) {
         debug!("Grabbing button {} for window: {}", window_id, button);
 
-        // Register button to grab with X. We don't bother checking as only ever for root window
-        xcb::grab_button(
-            self.conn,
-            false,                                       // owner events (a.k. don't pass on events to root window)
-            window_id,                                   // window id
-            mask as u16,                                 // button event mask
-            xcb::GRAB_MODE_ASYNC as u8,                  // pointer mode
-            xcb::GRAB_MODE_ASYNC as u8,                  // keyboard mode
-            if confine { window_id } else { xcb::NONE }, // confine pointer to window (or no confine)
-            xcb::NONE,                                   // cursor to use
-            button as u8,                                // button to grab (right click)
-            modmask as u16,                              // Modifiers to grab mouse with
-        );
+        // Register button to grab with X, with every lock modifier combination. We don't bother checking as only ever for root window.
+        // Pointer mode is async so the pointer never freezes, meaning no allow_events needed after a ButtonPress
+        for lock_mask in helper::LOCK_MASK_COMBINATIONS.iter() {
+            xcb::grab_button(
+                self.conn,
+                false,                                       // owner events (a.k. don't pass on events to root window)
+                window_id,                                   // window id
+                mask as u16,                                 // button event mask
+                xcb::GRAB_MODE_ASYNC as u8,                  // pointer mode
+                xcb::GRAB_MODE_ASYNC as u8,                  // keyboard mode
+                if confine { window_id } else { xcb::NONE }, // confine pointer to window (or no confine)
+                xcb::NONE,                                   // cursor to use
+                button as u8,                                // button to grab (right click)
+                (modmask|lock_mask) as u16,                  // Modifiers to grab mouse with
+            );
+        }
     }
 
-    pub fn grab_pointer(&self, window_id: XWindowID, mask: xcb::EventMask) {
+    pub fn grab_pointer(&self, window_id: XWindowID, mask: xcb::EventMask, cursor: CursorIndex) {
         debug!("Grabbing pointer for window: {}", window_id);
 
+        // Get the cursor id at index in the stored cursors array
+        let cursor_id = self.cursors[cursor as usize];
+
         // Register to grab pointer. We don't bother checking as only ever for root window
         xcb::grab_pointer(
             self.conn,
@@ -270,7 +818,7 @@ impl<'a> XConn<'a> {
             xcb::GRAB_MODE_ASYNC as u8,                  // pointer mode
             xcb::GRAB_MODE_ASYNC as u8,                  // keyboard mode
             xcb::NONE,                                   // confine to window
-            xcb::NONE,                                   // cursor to display
+            cursor_id,                                   // cursor to display
             xcb::CURRENT_TIME,                           // time
         );
     }
@@ -320,12 +868,264 @@ impl<'a> XConn<'a> {
         }
     }
 
-    pub fn query_pointer(&self, window_id: XWindowID) -> (i32, i32, XWindowID) {
-        debug!("Querying pointer location for window: {}", window_id);
+    pub fn get_wm_desktop(&self, window_id: XWindowID) -> Option<usize> {
+        debug!("Getting wm desktop for window: {}", window_id);
+        match ewmh::get_wm_desktop(self.conn, window_id).get_reply() {
+            Ok(idx) => return Some(idx as usize),
+            Err(_) => return None,
+        }
+    }
+
+    pub fn get_wm_user_time(&self, window_id: XWindowID) -> Option<u32> {
+        debug!("Getting wm user time for window: {}", window_id);
+        match ewmh::get_wm_user_time(self.conn, window_id).get_reply() {
+            Ok(time) => return Some(time),
+            Err(_) => return None,
+        }
+    }
+
+    pub fn get_wm_pid(&self, window_id: XWindowID) -> Option<u32> {
+        debug!("Getting wm pid for window: {}", window_id);
+        match ewmh::get_wm_pid(self.conn, window_id).get_reply() {
+            Ok(pid) => return Some(pid),
+            Err(_) => return None,
+        }
+    }
+
+    pub fn get_text_property(&self, window_id: XWindowID, atom: xcb::Atom) -> Option<String> {
+        debug!("Getting text property for window: {}", window_id);
+        match xcb::get_property(self.conn, false, window_id, atom, xcb::ATOM_ANY, 0, u32::MAX).get_reply() {
+            Ok(reply) => {
+                let bytes = reply.value::<u8>();
+                if bytes.is_empty() {
+                    return None;
+                }
+
+                // STRING is Latin-1, which maps directly onto the first 256 code points.
+                // Anything else (UTF8_STRING, COMPOUND_TEXT) is best treated as UTF-8
+                if reply.type_() == xcb::ATOM_STRING {
+                    return Some(bytes.iter().map(|byte| *byte as char).collect());
+                }
+                return Some(String::from_utf8_lossy(bytes).into_owned());
+            },
+            Err(_) => return None,
+        }
+    }
+
+    pub fn get_window_title(&self, window_id: XWindowID) -> String {
+        debug!("Getting title for window: {}", window_id);
+
+        // Prefer EWMH UTF-8 name, falling back to ICCCM name
+        return self.get_text_property(window_id, self.atoms.WM_NAME)
+            .or_else(|| self.get_text_property(window_id, xcb::ATOM_WM_NAME))
+            .unwrap_or_else(|| "<unnamed>".to_owned());
+    }
+
+    pub fn get_wm_class(&self, window_id: XWindowID) -> Option<(String, String)> {
+        debug!("Getting wm class for window: {}", window_id);
+        match icccm::get_wm_class(self.conn, window_id).get_reply() {
+            Ok(reply) => return Some((reply.instance().to_owned(), reply.class().to_owned())),
+            Err(_) => return None,
+        }
+    }
+
+    pub fn get_wm_hints(&self, window_id: XWindowID) -> Option<WmHints> {
+        debug!("Getting wm hints for window: {}", window_id);
+        // Only the flags field is of use to us
+        return self.get_property32(window_id, xcb::ATOM_WM_HINTS, xcb::ATOM_WM_HINTS, 9).first().map(|flags| WmHints { flags: *flags });
+    }
+
+    #[cfg(feature = "xinerama")]
+    pub fn get_xinerama_screens(&self) -> Vec<(i32, i32, i32, i32)> {
+        debug!("Getting Xinerama screens");
+
+        // Inactive (or missing) extension means a single screen covering the root window
+        match xcb::xinerama::is_active(self.conn).get_reply() {
+            Ok(reply) if reply.state() != 0 => (),
+            _ => return Vec::new(),
+        }
+
+        match xcb::xinerama::query_screens(self.conn).get_reply() {
+            Ok(reply) => return reply.screen_info().map(|info| (info.x_org() as i32, info.y_org() as i32, info.width() as i32, info.height() as i32)).collect(),
+            Err(_) => return Vec::new(),
+        }
+    }
+
+    #[cfg(not(feature = "xinerama"))]
+    pub fn get_xinerama_screens(&self) -> Vec<(i32, i32, i32, i32)> {
+        return Vec::new();
+    }
+
+    #[cfg(feature = "randr")]
+    pub fn get_randr_monitors(&self, root_id: XWindowID) -> Vec<(i32, i32, i32, i32)> {
+        debug!("Getting RandR monitors");
+
+        // Missing (or pre 1.2) extension has no CRTCs to ask about
+        if !self.has_randr {
+            return Vec::new();
+        }
+        let resources = match xcb::randr::get_screen_resources_current(self.conn, root_id).get_reply() {
+            Ok(reply) => reply,
+            Err(_) => return Vec::new(),
+        };
+
+        // Ask for every CRTC before waiting on any of them
+        let cookies: Vec<_> = resources.crtcs().iter().map(|crtc| xcb::randr::get_crtc_info(self.conn, *crtc, resources.config_timestamp())).collect();
+
+        // Disabled CRTCs have no mode, and mirrored outputs share a geometry so only count once
+        let mut monitors = Vec::new();
+        for cookie in cookies {
+            if let Ok(info) = cookie.get_reply() {
+                let monitor = (info.x() as i32, info.y() as i32, info.width() as i32, info.height() as i32);
+                if info.mode() != 0 && info.width() > 0 && info.height() > 0 && !monitors.contains(&monitor) {
+                    monitors.push(monitor);
+                }
+            }
+        }
+        return monitors;
+    }
+
+    #[cfg(not(feature = "randr"))]
+    pub fn get_randr_monitors(&self, _root_id: XWindowID) -> Vec<(i32, i32, i32, i32)> {
+        return Vec::new();
+    }
+
+    pub fn get_monitors(&self, root_id: XWindowID) -> Vec<(i32, i32, i32, i32)> {
+        // RandR if the server has it, else Xinerama heads. Neither means no way of knowing monitor layout
+        let monitors = self.get_randr_monitors(root_id);
+        if !monitors.is_empty() {
+            return monitors;
+        }
+        return self.get_xinerama_screens();
+    }
+
+    #[cfg(feature = "shape")]
+    pub fn is_shaped(&self, window_id: XWindowID) -> bool {
+        if !self.has_shape {
+            return false;
+        }
+
+        debug!("Getting shape extents for window: {}", window_id);
+        match xcb::shape::query_extents(self.conn, window_id).get_reply() {
+            Ok(reply) => return reply.bounding_shaped(),
+            Err(_) => return false,
+        }
+    }
+
+    #[cfg(not(feature = "shape"))]
+    pub fn is_shaped(&self, _window_id: XWindowID) -> bool {
+        return false;
+    }
+
+    pub fn get_motif_decorations(&self, window_id: XWindowID) -> Option<bool> {
+        debug!("Getting motif decorations for window: {}", window_id);
+        return motif_decorations(&self.get_property32(window_id, self.atoms.MOTIF_WM_HINTS, self.atoms.MOTIF_WM_HINTS, 5));
+    }
+
+    pub fn get_strut_partial(&self, window_id: XWindowID) -> Option<[i32; 12]> {
+        debug!("Getting partial strut for window: {}", window_id);
 
-        // We don't bother requesting check here as this is only ever used for root window
-        let pointer = xcb::query_pointer(self.conn, window_id).get_reply().expect("Querying window pointer location");
-        return (pointer.root_x() as i32, pointer.root_y() as i32, pointer.child())
+        // (left, right, top, bottom) widths, then (start, end) extents along each edge
+        let values = self.get_property32(window_id, self.atoms.WM_STRUT_PARTIAL, xcb::ATOM_CARDINAL, 12);
+        if values.len() < 12 {
+            return None;
+        }
+        let mut strut = [0; 12];
+        for (value, strut) in values.iter().zip(strut.iter_mut()) {
+            *strut = *value as i32;
+        }
+        return Some(strut);
+    }
+
+    pub fn get_strut(&self, window_id: XWindowID) -> Option<[i32; 4]> {
+        debug!("Getting strut for window: {}", window_id);
+
+        // Old style (left, right, top, bottom) widths only, spanning the whole edge
+        match self.get_property32(window_id, self.atoms.WM_STRUT, xcb::ATOM_CARDINAL, 4).as_slice() {
+            [left, right, top, bottom] => return Some([*left as i32, *right as i32, *top as i32, *bottom as i32]),
+            _ => return None,
+        }
+    }
+
+    pub fn get_transient_for(&self, window_id: XWindowID) -> Option<XWindowID> {
+        debug!("Getting transient for window: {}", window_id);
+        return self.get_property32(window_id, xcb::ATOM_WM_TRANSIENT_FOR, xcb::ATOM_WINDOW, 1).first().copied().filter(|parent_id| *parent_id != xcb::NONE);
+    }
+
+    pub fn get_size_hints(&self, window_id: XWindowID) -> Option<SizeHints> {
+        debug!("Getting size hints for window: {}", window_id);
+        let values = self.get_property32(window_id, xcb::ATOM_WM_NORMAL_HINTS, xcb::ATOM_WM_SIZE_HINTS, 18);
+        if values.is_empty() {
+            return None;
+        }
+        return Some(SizeHints { values: values });
+    }
+
+    fn get_property32(&self, window_id: XWindowID, atom: xcb::Atom, type_: xcb::Atom, len: u32) -> Vec<u32> {
+        // Up to len 32-bit values. Empty if unset, or not actually 32-bit values of the type asked
+        // for (clients do get these wrong), rather than reinterpreting whatever bytes are there
+        match xcb::get_property(self.conn, false, window_id, atom, type_, 0, len).get_reply() {
+            Ok(reply) if is_property32(reply.format(), reply.type_(), type_) => return reply.value::<u32>().to_owned(),
+            _ => return Vec::new(),
+        }
+    }
+
+    #[cfg_attr(feature="debug-sync", track_caller)]
+    pub fn set_cardinals(&self, window_id: XWindowID, atom: xcb::Atom, values: &[u32]) {
+        debug!("Setting cardinal property for window: {}", window_id);
+
+        // Replace property with values. Don't bother checking, if it failed, it failed :shrug:
+        request!(xcb::change_property, xcb::change_property_checked, self.conn, xcb::PROP_MODE_REPLACE as u8, window_id, atom, xcb::ATOM_CARDINAL, 32, values);
+    }
+
+    pub fn set_utf8_string(&self, window_id: XWindowID, atom: xcb::Atom, value: &str) {
+        debug!("Setting UTF-8 string property for window: {}", window_id);
+
+        // Replace property with value, same deal as cardinals
+        xcb::change_property(self.conn, xcb::PROP_MODE_REPLACE as u8, window_id, atom, self.atoms.UTF8_STRING, 8, value.as_bytes());
+    }
+
+    pub fn set_urgency(&self, window_id: XWindowID, urgent: bool) {
+        debug!("Setting urgency {} for window: {}", urgent, window_id);
+
+        // Keep the rest of WM_HINTS as the client set it, starting from nothing if unset
+        let mut hints = self.get_property32(window_id, xcb::ATOM_WM_HINTS, xcb::ATOM_WM_HINTS, 9);
+        hints.resize(9, 0);
+        if urgent {
+            hints[0] |= WM_HINTS_URGENCY;
+        } else {
+            hints[0] &= !WM_HINTS_URGENCY;
+        }
+
+        // Write back, the resulting property notify updates our state as if the client set it
+        xcb::change_property(self.conn, xcb::PROP_MODE_REPLACE as u8, window_id, xcb::ATOM_WM_HINTS, xcb::ATOM_WM_HINTS, 32, &hints);
+    }
+
+    pub fn get_urgency(&self, window_id: XWindowID) -> bool {
+        // Urgent if WM_HINTS has the urgency flag set
+        return self.get_wm_hints(window_id).map_or(false, |hints| hints.is_urgent());
+    }
+
+    pub fn get_wm_state(&self, window_id: XWindowID) -> Vec<xcb::Atom> {
+        debug!("Getting wm state for window: {}", window_id);
+        match ewmh::get_wm_state(self.conn, window_id).get_reply() {
+            Ok(reply) => return reply.atoms().to_owned(),
+            Err(_) => return Vec::new(),
+        }
+    }
+
+    pub fn set_wm_state(&self, window_id: XWindowID, states: &[xcb::Atom]) {
+        debug!("Setting wm state for window: {}", window_id);
+
+        // Set EWMH window state. Don't bother checking, if it failed, it failed :shrug:
+        ewmh::set_wm_state(self.conn, window_id, states);
+    }
+
+    pub fn warp_pointer(&self, window_id: XWindowID, x: i32, y: i32) {
+        debug!("Warping pointer to window: {} {} {}", window_id, x, y);
+
+        // Move pointer to position relative to window. Don't bother checking, if it failed, it failed :shrug:
+        xcb::warp_pointer(self.conn, xcb::NONE, window_id, 0, 0, 0, 0, x as i16, y as i16);
     }
 
     #[cfg(debug_assertions)]
@@ -338,19 +1138,108 @@ impl<'a> XConn<'a> {
         // Get keysym for event
         let keysym = self.key_syms.press_lookup_keysym(event, 0);
 
-        // Create new tuple of (mod_mask, key_sym)
-        return (event.state() as u32, keysym);
+        // Create new tuple of (mod_mask, key_sym), ignoring lock modifiers
+        return (helper::clean_mask(event.state()), keysym);
+    }
+
+    pub fn ungrab_all_buttons(&self, window_id: XWindowID) {
+        debug!("Ungrabbing all buttons for window: {}", window_id);
+
+        // Unregister all button grabs on window, any button with any modifiers. We don't bother checking as only ever for root window
+        xcb::ungrab_button(self.conn, xcb::BUTTON_INDEX_ANY as u8, window_id, xcb::MOD_MASK_ANY as u16);
+    }
+
+    pub fn flush(&self) {
+        // Flush connection, sending all queued requests
+        self.conn.flush();
     }
 
-    pub fn next_event(&self) -> xcb::GenericEvent {
+    pub fn next_event(&self, wake_fds: &[RawFd], timeout: i32) -> Option<xcb::GenericEvent> {
         // Flush connection to ensure clean
         self.conn.flush();
 
-        // Check for queued, else wait for next
-        return if let Some(event) = self.conn.poll_for_queued_event() {
-            event
-        } else {
-            self.conn.wait_for_event().expect("I/O error getting event from X server")
-        };
+        // Check for queued first
+        if let Some(event) = self.conn.poll_for_queued_event() {
+            return Some(event);
+        }
+
+        // Wait until either the X connection or any wake fd are readable, or timeout (ms, -1 for none)
+        let mut fds = vec![libc::pollfd { fd: self.conn.as_raw_fd(), events: libc::POLLIN, revents: 0 }];
+        for fd in wake_fds {
+            fds.push(libc::pollfd { fd: *fd, events: libc::POLLIN, revents: 0 });
+        }
+        unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout); }
+
+        // Check for a connection error before reading
+        if self.conn.has_error().is_err() {
+            fatal!("I/O error getting event from X server");
+        }
+
+        // Read next event, if any
+        return self.conn.poll_for_event();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn center_relative_to_window() {
+        let mut xwindow = XWindow::from(1);
+        xwindow.x = 1920;
+        xwindow.y = 40;
+        xwindow.width = 801;
+        xwindow.height = 600;
+        assert_eq!(xwindow.center(), (400, 300));
+    }
+
+    #[test]
+    fn motif_decorations_flagged() {
+        // Only if the decorations field is flagged as set
+        assert_eq!(motif_decorations(&[MOTIF_HINTS_DECORATIONS, 0, 0, 0, 0]), Some(false));
+        assert_eq!(motif_decorations(&[MOTIF_HINTS_DECORATIONS, 0, 1, 0, 0]), Some(true));
+        assert_eq!(motif_decorations(&[0, 0, 0, 0, 0]), None);
+
+        // Missing or short property
+        assert_eq!(motif_decorations(&[MOTIF_HINTS_DECORATIONS, 0]), None);
+        assert_eq!(motif_decorations(&[]), None);
+    }
+
+    #[test]
+    fn atom_cache_consistent() {
+        // Each interned once, up front
+        let mut cache = AtomCache::default();
+        assert_eq!(cache.uncached(CACHED_ATOMS), CACHED_ATOMS.to_vec());
+        assert_eq!(cache.uncached(&["WM_DELETE_WINDOW", "WM_DELETE_WINDOW"]), vec!["WM_DELETE_WINDOW"]);
+
+        // Once cached, the same id every time without asking X again
+        cache.atoms.insert("UTF8_STRING", 300);
+        assert_eq!(cache.cached("UTF8_STRING"), Some(300));
+        assert_eq!(cache.cached("UTF8_STRING"), Some(300));
+        assert!(!cache.uncached(CACHED_ATOMS).contains(&"UTF8_STRING"));
+        assert_eq!(cache.cached("_NET_WM_WINDOW_OPACITY"), None);
+    }
+
+    #[test]
+    fn property32_checked() {
+        assert!(is_property32(32, xcb::ATOM_CARDINAL, xcb::ATOM_CARDINAL));
+
+        // Wrong format or type, e.g. a client setting 8-bit values, isn't reinterpreted
+        assert!(!is_property32(8, xcb::ATOM_CARDINAL, xcb::ATOM_CARDINAL));
+        assert!(!is_property32(32, xcb::ATOM_WINDOW, xcb::ATOM_CARDINAL));
+
+        // Fewer values than asked for, missing fields are unset
+        let hints = SizeHints { values: vec![SIZE_HINTS_RESIZE_INC|SIZE_HINTS_ASPECT|SIZE_HINTS_BASE_SIZE, 0, 0, 0, 0, 0, 0, 0, 0, 10] };
+        assert_eq!(hints.resize_inc(), None);
+        assert_eq!(hints.aspect(), (None, None));
+        assert_eq!(hints.base_size(), (0, 0));
+    }
+
+    #[test]
+    fn wm_hints_urgency() {
+        assert!(WmHints { flags: WM_HINTS_URGENCY }.is_urgent());
+        assert!(WmHints { flags: WM_HINTS_URGENCY|1 }.is_urgent());
+        assert!(!WmHints { flags: 1 }.is_urgent());
     }
 }